@@ -0,0 +1,265 @@
+use crate::stats::{percentile, Histogram, LOSS_TIMEOUT};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Periods and number of buckets of the tiers kept for each target, from finest to coarsest
+const TIERS: [(Duration, usize); 2] = [
+    (Duration::from_secs(1), 3 * 3600), // 3 hours
+    (Duration::from_secs(60), 48 * 60), // 2 days
+];
+
+/// Latency statistics of the pings sent during a period of time
+#[derive(Clone, Debug)]
+pub struct Bucket {
+    /// Time at which the period starts
+    pub start: Instant,
+    pub sent: u32,
+    pub received: u32,
+    pub min: Option<Duration>,
+    pub max: Option<Duration>,
+    pub total: Duration,
+    /// Only known once the bucket is finished
+    pub p99: Option<Duration>,
+    pub histogram: Histogram,
+    /// Latencies received, kept until the bucket is finished
+    samples: Vec<Duration>,
+    finished: bool,
+}
+
+/// Buckets covering consecutive periods of the same length
+#[derive(Debug)]
+pub struct Tier {
+    pub period: Duration,
+    capacity: usize,
+    buckets: VecDeque<Bucket>,
+}
+
+/// Downsampled history of a target, to show longer time spans than the ring buffer can hold
+#[derive(Debug)]
+pub struct Aggregates {
+    tiers: Vec<Tier>,
+}
+
+impl Bucket {
+    pub fn new(start: Instant) -> Bucket {
+        Bucket {
+            start,
+            sent: 0,
+            received: 0,
+            min: None,
+            max: None,
+            total: Duration::ZERO,
+            p99: None,
+            histogram: Histogram::default(),
+            samples: Vec::new(),
+            finished: false,
+        }
+    }
+
+    pub fn sent(&mut self) {
+        self.sent += 1;
+    }
+
+    pub fn received(&mut self, latency: Duration) {
+        self.received += 1;
+        self.total += latency;
+        self.min = Some(self.min.map_or(latency, |min| min.min(latency)));
+        self.max = Some(self.max.map_or(latency, |max| max.max(latency)));
+        self.histogram.add(latency);
+        if !self.finished {
+            self.samples.push(latency);
+        }
+    }
+
+    /// Computes the percentiles once no more responses are expected for this period
+    pub fn finish(&mut self) {
+        self.p99 = percentile(&mut self.samples, 0.99);
+        self.samples = Vec::new();
+        self.finished = true;
+    }
+
+    /// Whether the loss count is final
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn avg(&self) -> Option<Duration> {
+        self.total.checked_div(self.received)
+    }
+
+    pub fn lost(&self) -> u32 {
+        self.sent.saturating_sub(self.received)
+    }
+}
+
+impl Tier {
+    fn new(period: Duration, capacity: usize) -> Tier {
+        Tier {
+            period,
+            capacity,
+            buckets: VecDeque::new(),
+        }
+    }
+
+    fn sent(&mut self, time: Instant) {
+        match self.buckets.back_mut() {
+            Some(bucket) if time < bucket.start + self.period => bucket.sent(),
+            last => {
+                // Periods are aligned on the first one, even after a pause
+                let start = match last {
+                    Some(bucket) => {
+                        let periods = (time - bucket.start).as_nanos() / self.period.as_nanos();
+                        bucket.start + self.period * periods as u32
+                    }
+                    None => time,
+                };
+                let mut bucket = Bucket::new(start);
+                bucket.sent();
+                self.buckets.push_back(bucket);
+                if self.buckets.len() > self.capacity {
+                    self.buckets.pop_front();
+                }
+            }
+        }
+
+        for bucket in self.buckets.iter_mut().rev() {
+            if bucket.is_finished() {
+                break;
+            } else if bucket.start + self.period + LOSS_TIMEOUT <= time {
+                bucket.finish();
+            }
+        }
+    }
+
+    fn received(&mut self, sent_time: Instant, latency: Duration) {
+        let i = self.buckets.partition_point(|b| b.start <= sent_time);
+        if i > 0 {
+            self.buckets[i - 1].received(latency);
+        }
+    }
+
+    /// Buckets from oldest to latest
+    pub fn buckets(&self) -> &VecDeque<Bucket> {
+        &self.buckets
+    }
+}
+
+impl Aggregates {
+    pub fn new() -> Aggregates {
+        Aggregates {
+            tiers: TIERS
+                .iter()
+                .map(|&(period, capacity)| Tier::new(period, capacity))
+                .collect(),
+        }
+    }
+
+    pub fn sent(&mut self, time: Instant) {
+        for tier in &mut self.tiers {
+            tier.sent(time);
+        }
+    }
+
+    pub fn received(&mut self, sent_time: Instant, latency: Duration) {
+        for tier in &mut self.tiers {
+            tier.received(sent_time, latency);
+        }
+    }
+
+    /// Tiers from finest to coarsest
+    pub fn tiers(&self) -> &[Tier] {
+        &self.tiers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn starts(tier: &Tier, origin: Instant) -> Vec<Duration> {
+        tier.buckets().iter().map(|b| b.start - origin).collect()
+    }
+
+    #[test]
+    fn periods_are_aligned() {
+        let start = Instant::now();
+        let mut tier = Tier::new(Duration::from_secs(1), 10);
+        tier.sent(start);
+        tier.sent(start + ms(999));
+        tier.sent(start + ms(1000));
+        // After a pause, the new bucket starts on a multiple of the period
+        tier.sent(start + ms(3700));
+        assert_eq!(starts(&tier, start), [ms(0), ms(1000), ms(3000)]);
+        let sent: Vec<_> = tier.buckets().iter().map(|b| b.sent).collect();
+        assert_eq!(sent, [2, 1, 1]);
+    }
+
+    #[test]
+    fn finished_after_loss_timeout() {
+        let start = Instant::now();
+        let mut tier = Tier::new(Duration::from_secs(1), 10);
+        tier.sent(start);
+        tier.received(start, ms(20));
+        tier.received(start, ms(40));
+        tier.sent(start + ms(1000) + LOSS_TIMEOUT - ms(1));
+        assert!(!tier.buckets()[0].is_finished());
+        assert_eq!(tier.buckets()[0].p99, None);
+
+        tier.sent(start + ms(1000) + LOSS_TIMEOUT);
+        let bucket = &tier.buckets()[0];
+        assert!(bucket.is_finished());
+        assert_eq!(bucket.p99, Some(ms(40)));
+        assert_eq!(bucket.avg(), Some(ms(30)));
+        assert_eq!((bucket.min, bucket.max), (Some(ms(20)), Some(ms(40))));
+        assert!(!tier.buckets()[1].is_finished());
+    }
+
+    #[test]
+    fn late_responses() {
+        let start = Instant::now();
+        let mut tier = Tier::new(Duration::from_secs(1), 10);
+        tier.sent(start + ms(100));
+        tier.sent(start + ms(1100));
+        tier.sent(start + ms(2100));
+        // Counted in the bucket of the ping, whichever bucket is the latest
+        tier.received(start + ms(1100), ms(1500));
+        tier.received(start + ms(2100), ms(10));
+        // Sent before the first bucket, nowhere to count it
+        tier.received(start, ms(10));
+        let received: Vec<_> = tier.buckets().iter().map(|b| b.received).collect();
+        assert_eq!(received, [0, 1, 1]);
+        assert_eq!(tier.buckets()[0].lost(), 1);
+        assert_eq!(tier.buckets()[1].max, Some(ms(1500)));
+    }
+
+    #[test]
+    fn oldest_buckets_evicted() {
+        let start = Instant::now();
+        let mut tier = Tier::new(Duration::from_secs(1), 3);
+        for i in 0..5 {
+            tier.sent(start + ms(i * 1000));
+        }
+        assert_eq!(starts(&tier, start), [ms(2000), ms(3000), ms(4000)]);
+    }
+
+    #[test]
+    fn all_tiers_updated() {
+        let start = Instant::now();
+        let mut aggregates = Aggregates::new();
+        for i in 0..90 {
+            aggregates.sent(start + Duration::from_secs(i));
+            aggregates.received(start + Duration::from_secs(i), ms(10));
+        }
+        let tiers = aggregates.tiers();
+        assert_eq!(tiers[0].buckets().len(), 90);
+        assert_eq!(tiers[1].buckets().len(), 2);
+        assert_eq!(tiers[1].buckets()[0].received, 60);
+        assert_eq!(tiers[1].buckets()[1].received, 30);
+    }
+}
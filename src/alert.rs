@@ -0,0 +1,380 @@
+use crate::{
+    ringbuf::{Ping, RingBuffer},
+    stats::LOSS_TIMEOUT,
+};
+use std::{
+    process::Command,
+    time::{Duration, Instant},
+};
+
+use log::*;
+
+/// A condition on the latest pings of a target that raises an alert
+#[cfg_attr(
+    feature = "config",
+    derive(serde_derive::Serialize, serde_derive::Deserialize),
+    serde(tag = "type", rename_all = "kebab-case")
+)]
+#[derive(Clone, Debug, PartialEq)]
+pub enum AlertRule {
+    /// Average latency of the responses to the last `pings` pings above `ms` milliseconds
+    Latency { ms: f64, pings: usize },
+    /// More than `percent` percent of the last `pings` pings lost
+    Loss { percent: f64, pings: usize },
+    /// No response to the pings sent over the last `secs` seconds, or no response at all while
+    /// the host can't be resolved
+    NoResponse { secs: f64 },
+}
+
+/// Number of pings a rule looks at when not given on the command line
+const DEFAULT_RULE_PINGS: usize = 10;
+
+/// Delay between two checks of the rules when no ping is sent or received, e.g while the host
+/// can't be resolved
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether a target is being alerted on, and why
+#[derive(Debug)]
+pub struct AlertState {
+    reason: Option<String>,
+    /// Time from which silences are counted, when the target started or resumed being probed
+    since: Instant,
+}
+
+impl AlertRule {
+    /// Parses a rule from the command line, as `latency:<ms>[:<pings>]`, `loss:<percent>[:<pings>]`
+    /// or `no-response:<secs>`
+    pub fn parse(arg: &str) -> Result<Self, String> {
+        let mut parts = arg.split(':');
+        let kind = parts.next().unwrap_or_default();
+        let threshold = match parts.next().map(str::parse::<f64>) {
+            Some(Ok(threshold)) if threshold >= 0. && threshold.is_finite() => threshold,
+            _ => return Err(String::from("expected a threshold after the rule type")),
+        };
+        let pings = match parts.next().map(str::parse::<usize>) {
+            None => None,
+            Some(Ok(pings)) if pings > 0 => Some(pings),
+            Some(_) => {
+                return Err(String::from(
+                    "expected a number of pings after the threshold",
+                ))
+            }
+        };
+        if parts.next().is_some() {
+            return Err(String::from("too many values"));
+        }
+        match kind {
+            "latency" => Ok(AlertRule::Latency {
+                ms: threshold,
+                pings: pings.unwrap_or(DEFAULT_RULE_PINGS),
+            }),
+            "loss" if threshold > 100. => Err(String::from("expected a percentage up to 100")),
+            "loss" => Ok(AlertRule::Loss {
+                percent: threshold,
+                pings: pings.unwrap_or(DEFAULT_RULE_PINGS),
+            }),
+            "no-response" if pings.is_some() => Err(String::from(
+                "no-response rules don't take a number of pings",
+            )),
+            "no-response" => Ok(AlertRule::NoResponse { secs: threshold }),
+            _ => Err(format!("unknown rule type '{}'", kind)),
+        }
+    }
+
+    /// Why the rule is triggered by the given pings, if it is. Silences are counted from `since`
+    /// at the earliest.
+    pub fn check(&self, ringbuf: &RingBuffer, since: Instant, now: Instant) -> Option<String> {
+        match *self {
+            AlertRule::Latency { ms, pings } => {
+                let latencies: Vec<Duration> = ringbuf
+                    .iter_rev()
+                    .take(pings)
+                    .filter_map(|ping| match ping {
+                        Ping::Received(_, lat) => Some(lat),
+                        Ping::Sent(_) => None,
+                    })
+                    .collect();
+                let avg = latencies
+                    .iter()
+                    .sum::<Duration>()
+                    .checked_div(latencies.len() as u32)?;
+                let avg_ms = avg.as_secs_f64() * 1000.;
+                if avg_ms > ms {
+                    Some(format!("average latency {:.1}ms > {}ms", avg_ms, ms))
+                } else {
+                    None
+                }
+            }
+            AlertRule::Loss { percent, pings } => {
+                // Pings still awaiting a response don't count yet
+                let (sent, lost) = ringbuf
+                    .iter_rev()
+                    .filter(|ping| match ping {
+                        Ping::Sent(time) => now.saturating_duration_since(*time) >= LOSS_TIMEOUT,
+                        Ping::Received(..) => true,
+                    })
+                    .take(pings)
+                    .fold((0, 0), |(sent, lost), ping| match ping {
+                        Ping::Sent(_) => (sent + 1, lost + 1),
+                        Ping::Received(..) => (sent + 1, lost),
+                    });
+                let loss = lost as f64 / sent as f64 * 100.;
+                if sent == pings && loss > percent {
+                    Some(format!("loss {:.0}% > {}%", loss, percent))
+                } else {
+                    None
+                }
+            }
+            AlertRule::NoResponse { secs } => {
+                // Silent since the last response, or since the oldest ping left unanswered after it
+                // if that's later. No need to look further back than a ping unanswered for long
+                // enough.
+                let mut silence_start = None;
+                for ping in ringbuf.iter_rev() {
+                    match ping {
+                        Ping::Received(time, lat) => {
+                            let response = time + lat;
+                            silence_start =
+                                Some(silence_start.map_or(response, |t| response.max(t)));
+                            break;
+                        }
+                        Ping::Sent(time) => {
+                            silence_start = Some(time);
+                            if now.saturating_duration_since(time).as_secs_f64() >= secs {
+                                break;
+                            }
+                        }
+                    }
+                }
+                let silence =
+                    now.saturating_duration_since(silence_start.unwrap_or(since).max(since));
+                if silence.as_secs_f64() >= secs {
+                    Some(format!("no response for {}s", silence.as_secs()))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+impl AlertState {
+    pub fn new(since: Instant) -> Self {
+        AlertState {
+            reason: None,
+            since,
+        }
+    }
+
+    /// Counts silences from the given time, when pings are sent again after a pause
+    pub fn resume(&mut self, since: Instant) {
+        self.since = since;
+    }
+
+    /// Checks the rules against the target's pings, returning whether the alert was raised or
+    /// cleared
+    pub fn update(&mut self, rules: &[AlertRule], ringbuf: &RingBuffer, now: Instant) -> bool {
+        let was_raised = self.reason.is_some();
+        self.reason = rules
+            .iter()
+            .find_map(|rule| rule.check(ringbuf, self.since, now));
+        was_raised != self.reason.is_some()
+    }
+
+    /// Why the alert is raised, or None if it isn't
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+}
+
+/// Runs the user's command in a shell without waiting for it, with the target and the reason for
+/// the alert (empty once cleared) in the `LATGRAPH_TARGET` and `LATGRAPH_ALERT` variables
+pub fn run_hook(command: &str, target: &str, reason: Option<&str>) {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let spawned = shell
+        .arg(command)
+        .env("LATGRAPH_TARGET", target)
+        .env("LATGRAPH_ALERT", reason.unwrap_or_default())
+        .spawn();
+    match spawned {
+        // Reap the process once it's done
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        }
+        Err(e) => error!("Couldn't run alert command '{}': {}", command, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    /// Buffer of pings sent 100ms apart from `start`, answered after the given latencies in
+    /// milliseconds, or not at all for `None`
+    fn pings(start: Instant, latencies: &[Option<u64>]) -> RingBuffer {
+        let mut buf = RingBuffer::new(100);
+        for (i, lat) in latencies.iter().enumerate() {
+            let sent = start + ms(i as u64 * 100);
+            buf.sent(sent);
+            if let Some(lat) = lat {
+                buf.received(i as u64, sent + ms(*lat));
+            }
+        }
+        buf
+    }
+
+    #[test]
+    fn parse_rules() {
+        assert_eq!(
+            AlertRule::parse("latency:80:20"),
+            Ok(AlertRule::Latency { ms: 80., pings: 20 })
+        );
+        assert_eq!(
+            AlertRule::parse("loss:2.5"),
+            Ok(AlertRule::Loss {
+                percent: 2.5,
+                pings: DEFAULT_RULE_PINGS
+            })
+        );
+        assert_eq!(
+            AlertRule::parse("no-response:10"),
+            Ok(AlertRule::NoResponse { secs: 10. })
+        );
+        assert_eq!(
+            AlertRule::parse("loss:100:5"),
+            Ok(AlertRule::Loss {
+                percent: 100.,
+                pings: 5
+            })
+        );
+        for invalid in [
+            "",
+            "latency",
+            "latency:",
+            "latency:-1",
+            "latency:abc",
+            "latency:80:0",
+            "latency:80:x",
+            "latency:inf",
+            "latency:NaN",
+            "loss:5:10:3",
+            "loss:100.5",
+            "no-response:5:10",
+            "no-response:inf",
+            "jitter:5",
+        ] {
+            assert!(AlertRule::parse(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn latency() {
+        let start = Instant::now();
+        let now = start + Duration::from_secs(10);
+        let rule = AlertRule::Latency { ms: 50., pings: 3 };
+        let buf = pings(start, &[Some(10), Some(100), Some(100), Some(100)]);
+        assert!(rule.check(&buf, start, now).is_some());
+        let buf = pings(start, &[Some(100), Some(10), Some(10), Some(100)]);
+        assert_eq!(rule.check(&buf, start, now), None);
+        // Unanswered pings are left out of the average, but still count in the window
+        let buf = pings(start, &[Some(10), Some(100), None, None]);
+        assert!(rule.check(&buf, start, now).is_some());
+        let buf = pings(start, &[Some(100), None, None, None]);
+        assert_eq!(rule.check(&buf, start, now), None);
+    }
+
+    #[test]
+    fn loss() {
+        let start = Instant::now();
+        let rule = AlertRule::Loss {
+            percent: 40.,
+            pings: 4,
+        };
+        let now = start + Duration::from_secs(10);
+        let buf = pings(start, &[Some(10), None, Some(10), None, None]);
+        assert_eq!(
+            rule.check(&buf, start, now).as_deref(),
+            Some("loss 75% > 40%")
+        );
+        let buf = pings(start, &[None, Some(10), Some(10), Some(10), None]);
+        assert_eq!(rule.check(&buf, start, now), None);
+        // Not enough pings yet
+        let buf = pings(start, &[None, None, None]);
+        assert_eq!(rule.check(&buf, start, now), None);
+        // Pings sent less than LOSS_TIMEOUT ago aren't lost yet
+        let buf = pings(start, &[Some(10), Some(10), None, None, None, None]);
+        assert_eq!(rule.check(&buf, start, start + ms(1000)), None);
+    }
+
+    #[test]
+    fn no_response() {
+        let start = Instant::now();
+        let rule = AlertRule::NoResponse { secs: 2. };
+        // Responses stopped after the second ping, the silence starts with the third one
+        let buf = pings(start, &[Some(10), Some(10), None, None]);
+        assert_eq!(rule.check(&buf, start, start + ms(2150)), None);
+        assert_eq!(
+            rule.check(&buf, start, start + ms(2250)).as_deref(),
+            Some("no response for 2s")
+        );
+        // Answered recently
+        let buf = pings(start, &[None, None, Some(10)]);
+        assert_eq!(rule.check(&buf, start, start + ms(1000)), None);
+    }
+
+    #[test]
+    fn no_response_yet() {
+        let start = Instant::now();
+        let rule = AlertRule::NoResponse { secs: 2. };
+        // Nothing sent, e.g while the host can't be resolved
+        let buf = RingBuffer::new(10);
+        assert_eq!(rule.check(&buf, start, start + ms(1000)), None);
+        assert!(rule.check(&buf, start, start + ms(2500)).is_some());
+        // Silences are counted from when the target started
+        let buf = pings(start, &[None, None]);
+        assert_eq!(rule.check(&buf, start + ms(500), start + ms(2200)), None);
+        assert!(rule.check(&buf, start, start + ms(2200)).is_some());
+
+        let rules = [
+            AlertRule::Latency { ms: 1., pings: 3 },
+            AlertRule::Loss {
+                percent: 1.,
+                pings: 3,
+            },
+        ];
+        for rule in &rules {
+            assert_eq!(rule.check(&buf, start, start + ms(500)), None);
+            assert_eq!(
+                rule.check(&RingBuffer::new(10), start, start + ms(500)),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn state_changes() {
+        let start = Instant::now();
+        let rules = [AlertRule::NoResponse { secs: 1. }];
+        let mut state = AlertState::new(start);
+        let buf = RingBuffer::new(10);
+        assert!(!state.update(&rules, &buf, start + ms(500)));
+        assert!(state.update(&rules, &buf, start + ms(1500)));
+        assert_eq!(state.reason(), Some("no response for 1s"));
+        assert!(!state.update(&rules, &buf, start + ms(1800)));
+        state.resume(start + ms(2000));
+        assert!(state.update(&rules, &buf, start + ms(2500)));
+        assert_eq!(state.reason(), None);
+    }
+}
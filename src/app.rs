@@ -1,12 +1,23 @@
-use crate::{ringbuf::RingBuffer, widget::LatencyGraphWidget};
+use crate::{
+    aggregate::Aggregates,
+    alert::{self, AlertRule, AlertState},
+    export::{self, ExportFormat},
+    histogram::HistogramWidget,
+    metrics::Metrics,
+    probe::{self, packet, ProbeKind},
+    record::{Recorder, Recording, ReplayCommand},
+    ringbuf::RingBuffer,
+    settings_panel::{Field, SettingsPanel},
+    stats::Stats,
+    widget::{pixels_to_duration, LatencyGraphWidget, PlotStyle, YFit, YScale},
+};
 use std::{
+    fmt,
     hash::Hash,
-    io::Cursor,
-    net::UdpSocket,
+    io::{self, Cursor},
     path::PathBuf,
     sync::mpsc,
-    thread,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use conrod_core::{
@@ -18,7 +29,10 @@ use glium::{
     self,
     glutin::{
         dpi::LogicalSize,
-        event::{ElementState, Event, KeyboardInput, StartCause, VirtualKeyCode, WindowEvent},
+        event::{
+            ElementState, Event, KeyboardInput, ModifiersState, StartCause, VirtualKeyCode,
+            WindowEvent,
+        },
         event_loop::{ControlFlow, EventLoop, EventLoopProxy},
         window::WindowBuilder,
         ContextBuilder,
@@ -26,13 +40,11 @@ use glium::{
     Display, Surface, Texture2d,
 };
 use log::*;
-use thread_priority::ThreadPriority;
 use winit::window::Icon;
 
 pub struct LatGraphApp {
-    ringbuf: RingBuffer,
+    targets: Vec<Target>,
     settings: LatGraphSettings,
-    settings_tx: mpsc::Sender<LatGraphSettings>,
     config_path: Option<PathBuf>,
     display: Display,
     ui: Ui,
@@ -40,191 +52,300 @@ pub struct LatGraphApp {
     image_map: Map<Texture2d>,
     renderer: Renderer,
     is_mouse_over_window: bool,
+    /// Index of the target whose statistics are shown in the status bar
+    current_target: usize,
+    modifiers: ModifiersState,
+    /// File written when pressing E, a new one is created in the working directory if not set
+    export_path: Option<PathBuf>,
+    recorder: Option<Recorder>,
+    /// Counters served to Prometheus
+    metrics: Option<Metrics>,
+    /// Controls the replay thread when showing a recording instead of probing the targets
+    replay_tx: Option<mpsc::Sender<ReplayCommand>>,
+    /// Wall-clock time at which the replayed recording started
+    replay_start: Option<SystemTime>,
+    /// Position in the recording and whether it's paused, shown in the window title
+    replay_position: Option<(Duration, bool)>,
+    /// Time at the right edge of the graphs while the view is frozen, they follow the latest pings
+    /// otherwise
+    view_end: Option<Instant>,
+    /// Sends the events of the network threads to the event loop
+    event_proxy: EventLoopProxy<AppEvent>,
+    /// Whether the settings panel is open over the graphs
+    show_settings: bool,
+    /// Why the last value entered in the settings panel couldn't be applied
+    settings_error: Option<String>,
+    /// Whether a field of the settings panel has the keyboard focus
+    editing_settings: bool,
+    /// Id given to the next target whose network threads are started
+    next_target_id: usize,
 }
 
 #[cfg_attr(
     feature = "config",
     derive(serde_derive::Serialize, serde_derive::Deserialize)
 )]
-#[derive(Clone, Debug, Hash)]
+#[cfg_attr(feature = "config", serde(default))]
+#[derive(Clone, Debug)]
 pub struct LatGraphSettings {
     pub running: bool,
-    pub remote_host: String,
-    pub zoom: (u16, u16),
+    pub zoom: (i16, i16),
+    /// Size in bytes of the echo packets, padded after the header
+    pub payload_size: usize,
+    /// Label the horizontal axis with the time of day instead of the time span
+    pub clock_axis: bool,
+    /// Percentiles of the visible latencies marked on the vertical axis
+    pub percentiles: Vec<f64>,
+    /// Draw lines across the graph at the percentiles
+    pub percentile_lines: bool,
+    pub y_scale: YScale,
+    pub y_fit: YFit,
+    pub plot_style: PlotStyle,
+    /// Show the distribution of the visible latencies beside each graph
+    pub histogram: bool,
+    /// Color the bars by the threshold their latency falls under instead of the graph's color
+    pub threshold_colors: bool,
+    /// Shell command run when an alert is raised or cleared
+    pub alert_command: String,
+    /// Must stay before the tables for the config to serialize when it's a plain count
+    pub history: HistoryLength,
     pub delay: Duration,
+    #[cfg_attr(
+        feature = "config",
+        serde(alias = "remote_host", deserialize_with = "deserialize_targets")
+    )]
+    pub targets: Vec<TargetSettings>,
+    /// Latency thresholds marked on the graphs of targets that don't have their own, in increasing
+    /// order
+    #[cfg_attr(feature = "config", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub thresholds: Vec<Threshold>,
+    /// Conditions on the latest pings of each target that raise an alert
+    #[cfg_attr(feature = "config", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub alerts: Vec<AlertRule>,
+}
+
+/// Targets as saved, or the single host saved as `remote_host` before multiple targets could be
+/// monitored
+#[cfg(feature = "config")]
+#[derive(serde_derive::Deserialize)]
+#[serde(untagged)]
+enum SavedTargets {
+    List(Vec<TargetSettings>),
+    Host(String),
+}
+
+#[cfg(feature = "config")]
+fn deserialize_targets<'de, D>(deserializer: D) -> Result<Vec<TargetSettings>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(match serde::Deserialize::deserialize(deserializer)? {
+        SavedTargets::List(targets) => targets,
+        SavedTargets::Host(host) if host.is_empty() => Vec::new(),
+        SavedTargets::Host(host) => vec![TargetSettings {
+            host,
+            ..TargetSettings::default()
+        }],
+    })
+}
+
+/// How much of the past each graph keeps, either as a number of pings or as a time span. Saved as
+/// a plain number for counts, or like the delay for durations.
+#[cfg_attr(
+    feature = "config",
+    derive(serde_derive::Serialize, serde_derive::Deserialize),
+    serde(untagged)
+)]
+#[derive(Copy, Clone, Debug, Hash, PartialEq)]
+pub enum HistoryLength {
+    Count(usize),
+    Duration(Duration),
+}
+
+/// Upper bound on the number of pings kept per target, whatever the history length
+const MAX_HISTORY: usize = 1_000_000;
+/// Longest delay between two pings, much longer ones would overflow the times pings are due at
+pub const MAX_DELAY: Duration = Duration::from_secs(3600);
+/// Bounds of a history length given as a duration
+const MIN_HISTORY_DURATION: Duration = Duration::from_secs(1);
+const MAX_HISTORY_DURATION: Duration = Duration::from_secs(365 * 24 * 3600);
+
+/// A remote host to monitor, each target gets its own graph
+#[cfg_attr(
+    feature = "config",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[cfg_attr(feature = "config", serde(default))]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TargetSettings {
+    pub host: String,
+    /// Name displayed on the graph, the host is used if empty
+    pub label: String,
+    /// RGB color of the graph, picked from a default palette if not set
+    pub color: Option<[u8; 3]>,
+    pub probe: ProbeKind,
+    /// Latency thresholds of this target, replacing the global ones if set
+    #[cfg_attr(feature = "config", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub thresholds: Vec<Threshold>,
+}
+
+/// A range of latencies marked on the graphs, from the previous threshold up to this one
+#[cfg_attr(
+    feature = "config",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Threshold {
+    /// Upper limit in milliseconds, all the latencies above the previous thresholds if not set
+    pub below: Option<f64>,
+    pub color: [u8; 3],
+}
+
+/// Colors that can be given by name to the thresholds on the command line
+const COLOR_NAMES: [(&str, [u8; 3]); 7] = [
+    ("green", [80, 200, 80]),
+    ("yellow", [230, 200, 50]),
+    ("orange", [240, 140, 40]),
+    ("red", [220, 50, 40]),
+    ("blue", [70, 130, 230]),
+    ("purple", [160, 90, 200]),
+    ("grey", [150, 150, 150]),
+];
+
+/// Runtime state of a monitored target
+struct Target {
+    /// Id of the target in the events of its network threads
+    id: TargetId,
+    ringbuf: RingBuffer,
+    aggregates: Aggregates,
+    /// Statistics since the target was added
+    total: Stats,
+    settings_tx: mpsc::Sender<LatGraphSettings>,
+    invalid_packets: u64,
+    alert: AlertState,
+    /// Why the target isn't being probed, shown over its graph
+    error: Option<String>,
+    /// Host being typed in to replace one that couldn't be resolved
+    host_edit: Option<String>,
 }
 
 widget_ids! {
     struct Ids {
         canvas,
         grid,
-        graph,
-        status_bar
+        graphs[],
+        histograms[],
+        error_panels[],
+        error_texts[],
+        host_boxes[],
+        status_bar,
+        status_text,
+        settings_panel,
     }
 }
 
+/// Colors assigned to targets that don't have one configured
+const TARGET_COLORS: [color::Color; 6] = [
+    color::LIGHT_BLUE,
+    color::LIGHT_GREEN,
+    color::LIGHT_ORANGE,
+    color::LIGHT_PURPLE,
+    color::LIGHT_YELLOW,
+    color::LIGHT_RED,
+];
+
+/// Height of the status bar at the bottom of the window
+const STATUS_BAR_HEIGHT: f64 = 20.;
+
+/// Time skipped by the arrow keys when replaying a recording
+const REPLAY_SEEK_STEP: Duration = Duration::from_secs(10);
+
+/// Color of the graphs being alerted on, and of the status bar when it flashes
+const ALERT_COLOR: color::Color = color::RED;
+const ALERT_FLASH_COLOR: color::Color = color::DARK_RED;
+/// The status bar flashes on and off for this long each
+const ALERT_FLASH_PERIOD: Duration = Duration::from_millis(500);
+
+/// Width of the histograms beside the graphs
+const HISTOGRAM_PANEL_WIDTH: f64 = 80.;
+
+/// Fraction of the window's width panned by the arrow keys
+const PAN_STEP: f64 = 0.25;
+/// Size of the panel shown over a graph whose target can't be probed
+const ERROR_PANEL_SIZE: [f64; 2] = [400., 64.];
+
+/// Events sent from the network threads, each carrying the target they concern
 #[derive(Debug)]
-enum AppEvent {
-    Ping(Instant),
-    Pong(u64, Instant),
-    Error(AppError),
+pub enum AppEvent {
+    Ping(TargetId, Instant),
+    Pong(TargetId, u64, Instant),
+    InvalidPacket(TargetId),
+    Error(TargetId, AppError),
+    /// The target's host was resolved, after an error or a change
+    Connected(TargetId),
+    /// Current position in the replayed recording, and whether it's paused
+    ReplayStatus(Duration, bool),
+    /// The replay went back in time, the graphs must be cleared
+    ReplayReset,
 }
 
+/// Target of an event. Ids are given in order to the targets as their network threads start, so
+/// they match the indices of the targets until some are removed or restarted.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TargetId(pub usize);
+
 #[derive(Debug)]
-enum AppError {
-    HostResolution,
+pub enum AppError {
+    /// The host couldn't be resolved or connected to, the network thread tries again later
+    HostResolution(io::Error),
+    Socket(io::Error),
 }
 
-impl LatGraphApp {
-    pub fn start(settings: LatGraphSettings, config_path: Option<PathBuf>) {
-        let (settings_tx, settings_rx) = mpsc::channel();
+impl AppEvent {
+    /// Target the event is about, if any
+    fn target(&self) -> Option<TargetId> {
+        match *self {
+            AppEvent::Ping(target, _)
+            | AppEvent::Pong(target, ..)
+            | AppEvent::InvalidPacket(target)
+            | AppEvent::Error(target, _)
+            | AppEvent::Connected(target) => Some(target),
+            AppEvent::ReplayStatus(..) | AppEvent::ReplayReset => None,
+        }
+    }
+}
 
-        let (mut app, event_loop) = LatGraphApp::init_ui(settings_tx);
+impl LatGraphApp {
+    /// Opens the window and starts probing the targets, or replaying the recording if one is given
+    pub fn start(
+        settings: LatGraphSettings,
+        config_path: Option<PathBuf>,
+        export_path: Option<PathBuf>,
+        recorder: Option<Recorder>,
+        metrics: Option<Metrics>,
+        replay: Option<Recording>,
+    ) {
+        let (mut app, event_loop) = LatGraphApp::init_ui();
         app.config_path = config_path;
+        app.export_path = export_path;
+        app.recorder = recorder;
+        app.metrics = metrics;
 
-        LatGraphApp::init_network(settings_rx, event_loop.create_proxy());
+        if let Some(recording) = replay {
+            app.replay_start = Some(recording.start);
+            let (replay_tx, replay_rx) = mpsc::channel();
+            recording.replay(replay_rx, event_loop.create_proxy());
+            app.replay_tx = Some(replay_tx);
+        }
 
         app.settings = settings;
+        app.start_probes();
 
         info!("Starting event loop");
         app.run_loop(event_loop);
     }
 
-    fn init_network(
-        settings_rx: mpsc::Receiver<LatGraphSettings>,
-        event_tx_rcv: EventLoopProxy<AppEvent>,
-    ) {
-        debug!("Initializing network threads");
-        let socket_tx = UdpSocket::bind("0.0.0.0:0").expect("Couldn't bind network socket");
-        let socket_rx = socket_tx.try_clone().unwrap();
-        let event_tx_snd = event_tx_rcv.clone();
-
-        // Sender thread
-        thread::spawn(move || {
-            let event_tx = event_tx_snd;
-            let mut settings = LatGraphSettings::default();
-            let mut new_settings = false;
-            let mut new_remote = false;
-            let mut valid_remote = false; // Whether we managed to ever send a ping to the current remote
-            let mut next_ping = Instant::now();
-            let mut ping_id = 0u64;
-            if let Err(e) = ThreadPriority::Max.set_for_current() {
-                warn!("Couldn't set thread priority : {:?}", e);
-            }
-
-            loop {
-                if settings.running {
-                    debug!("SND: Sending ping");
-                    let now = Instant::now();
-                    if let Err(_) = event_tx.send_event(AppEvent::Ping(now)) {
-                        break;
-                    }
-                    if let Err(e) = socket_tx.send(&ping_id.to_ne_bytes()) {
-                        warn!("SND: Couldn't send ping ({}), attempting reconnect", e);
-
-                        let mut addr = settings.remote_host.clone();
-                        if !addr.contains(":") {
-                            addr += ":7";
-                        }
-                        if let Err(e) = socket_tx
-                            .connect(addr)
-                            .and_then(|_| socket_tx.send(&ping_id.to_ne_bytes()))
-                        {
-                            next_ping += Duration::from_secs(3);
-                            if valid_remote { // If we could send a ping to the host at least once, keep trying again
-                                error!("SND: Reconnect failed ({}), waiting 3s", e);
-                            } else { // Otherwise return a host resolution error
-                                error!("SND: Reconnect failed ({}), giving up", e);
-                                if let Err(_) =
-                                    event_tx.send_event(AppEvent::Error(AppError::HostResolution))
-                                {
-                                    break;
-                                }
-                                settings.running = false;
-                            }
-                        }
-                    } else {
-                        valid_remote = true;
-                    }
-                    ping_id += 1;
-                    next_ping = next_ping + settings.delay;
-                    if next_ping < Instant::now() {
-                        // If we're already past the next ping (process lagged a lot, computer went to sleep, etc),
-                        next_ping = Instant::now() + settings.delay;
-                    }
-                    thread::sleep(next_ping - Instant::now());
-
-                    // Poll for new settings, using 'while' in case there's multiple values queued
-                    for set in settings_rx.try_iter() {
-                        settings = set;
-                        new_settings = true;
-                    }
-                } else {
-                    match settings_rx.recv() {
-                        Ok(set) => {
-                            new_remote = set.remote_host != settings.remote_host;
-                            settings = set;
-                        }
-                        Err(_) => break, // Main thread is probably shutting down, just exit
-                    }
-                    new_settings = true;
-                }
-
-                if new_settings {
-                    debug!("SND: Received new settings {:#?}", settings);
-                    new_settings = false;
-
-                    // If remote host settings have changed
-                    if new_remote && !settings.remote_host.is_empty() {
-                        valid_remote = false;
-                        info!("SND: Connecting to new host");
-                        let mut addr = settings.remote_host.clone();
-                        if !addr.contains(":") {
-                            addr += ":7";
-                        }
-                        if let Err(e) = socket_tx.connect(addr) {
-                            error!("SND: Couldn't connect to host ({})", e);
-                            if let Err(_) =
-                                event_tx.send_event(AppEvent::Error(AppError::HostResolution))
-                            {
-                                break;
-                            }
-                            settings.running = false;
-                        }
-                        new_remote = false;
-                    }
-
-                    settings.running &= !settings.remote_host.is_empty();
-                }
-            }
-            debug!("SND: Stopping send thread");
-        });
-
-        // Receiver thread
-        thread::spawn(move || {
-            let event_tx = event_tx_rcv;
-            let mut buf = [0u8; 8];
-            if let Err(e) = ThreadPriority::Max.set_for_current() {
-                warn!("Couldn't set thread priority : {:?}", e);
-            }
-
-            loop {
-                match socket_rx.recv(&mut buf) {
-                    Ok(_) => {
-                        let id = u64::from_ne_bytes(buf);
-                        debug!("RCV: Received ping {}", id);
-                        if let Err(_) = event_tx.send_event(AppEvent::Pong(id, Instant::now())) {
-                            break;
-                        }
-                    }
-                    Err(e) => debug!("RCV: Got err on receiver thread : {}", e),
-                }
-            }
-            debug!("RCV: Stopping receiver thread");
-        });
-    }
-
-    fn init_ui(settings_tx: mpsc::Sender<LatGraphSettings>) -> (LatGraphApp, EventLoop<AppEvent>) {
+    fn init_ui() -> (LatGraphApp, EventLoop<AppEvent>) {
         const WIDTH: u32 = 800;
         const HEIGHT: u32 = 400;
         let font_data = include_bytes!("resources/WorkSans-Regular.ttf");
@@ -257,9 +378,8 @@ impl LatGraphApp {
 
         (
             LatGraphApp {
-                ringbuf: RingBuffer::new(1000),
+                targets: Vec::new(),
                 settings: LatGraphSettings::default(),
-                settings_tx,
                 config_path: None,
                 display,
                 ui,
@@ -267,13 +387,39 @@ impl LatGraphApp {
                 image_map,
                 renderer,
                 is_mouse_over_window: false,
+                current_target: 0,
+                modifiers: ModifiersState::empty(),
+                export_path: None,
+                recorder: None,
+                metrics: None,
+                replay_tx: None,
+                replay_start: None,
+                replay_position: None,
+                view_end: None,
+                event_proxy: event_loop.create_proxy(),
+                show_settings: false,
+                settings_error: None,
+                editing_settings: false,
+                next_target_id: 0,
             },
             event_loop,
         )
     }
 
     fn set_ui(&mut self, needs_redraw: &mut bool) {
-        let ui = &mut self.ui.set_widgets();
+        let status_text = self.status_text();
+        let mut ui_cell = self.ui.set_widgets();
+        let ui = &mut ui_cell;
+
+        let nb_graphs = self.targets.len();
+        if self.widget_ids.graphs.len() < nb_graphs {
+            let id_gen = &mut ui.widget_id_generator();
+            self.widget_ids.graphs.resize(nb_graphs, id_gen);
+            self.widget_ids.histograms.resize(nb_graphs, id_gen);
+            self.widget_ids.error_panels.resize(nb_graphs, id_gen);
+            self.widget_ids.error_texts.resize(nb_graphs, id_gen);
+            self.widget_ids.host_boxes.resize(nb_graphs, id_gen);
+        }
         let ids = &self.widget_ids;
 
         widget::Canvas::new()
@@ -281,16 +427,152 @@ impl LatGraphApp {
             .border(0.)
             .set(ids.canvas, ui);
 
-        self.settings.zoom =
-            LatencyGraphWidget::new(&self.ringbuf, &self.settings, self.is_mouse_over_window)
-                .color(color::LIGHT_BLUE)
-                .missing_color(color::rgba_bytes(192, 64, 32, 0.3))
-                .border_color(color::LIGHT_BLUE)
-                .wh_of(ids.canvas)
-                .middle_of(ids.canvas)
-                .set(ids.graph, ui);
+        // Graphs are stacked vertically above the status bar, each taking an equal share
+        let graphs_h = ui.h_of(ids.canvas).unwrap_or(0.) - STATUS_BAR_HEIGHT;
+        let graph_h = graphs_h.max(0.) / nb_graphs.max(1) as f64;
+        let histogram_w = if self.settings.histogram {
+            HISTOGRAM_PANEL_WIDTH
+        } else {
+            0.
+        };
+        let graph_w = (ui.w_of(ids.canvas).unwrap_or(0.) - histogram_w).max(0.);
+        let mut zoom = self.settings.zoom;
+        let mut drag = 0.;
+        let mut host_events = Vec::new();
+        for (i, (target, target_settings)) in self
+            .targets
+            .iter()
+            .zip(self.settings.targets.iter())
+            .enumerate()
+        {
+            let graph_color = target_settings.color(i);
+            let event =
+                LatencyGraphWidget::new(&target.ringbuf, &self.settings, self.is_mouse_over_window)
+                    .aggregates(&target.aggregates)
+                    .view_end(self.view_end)
+                    .thresholds(target_settings.thresholds(&self.settings.thresholds))
+                    .label(target_settings.display_label())
+                    .color(graph_color)
+                    .missing_color(color::rgba_bytes(192, 64, 32, 0.3))
+                    .border_color(if target.alert.reason().is_some() {
+                        ALERT_COLOR
+                    } else {
+                        graph_color
+                    })
+                    .w(graph_w)
+                    .h(graph_h)
+                    .top_left_with_margins_on(ids.canvas, i as f64 * graph_h, 0.)
+                    .set(ids.graphs[i], ui);
+            if self.settings.histogram {
+                HistogramWidget::new(&event.latencies, event.y_axis)
+                    .color(graph_color)
+                    .border_color(graph_color)
+                    .w(histogram_w)
+                    .h(graph_h)
+                    .top_right_with_margins_on(ids.canvas, i as f64 * graph_h, 0.)
+                    .set(ids.histograms[i], ui);
+            }
+            if let Some(error) = &target.error {
+                let panel_w = ERROR_PANEL_SIZE[0].min(graph_w - 20.).max(0.);
+                widget::Rectangle::fill([panel_w, ERROR_PANEL_SIZE[1]])
+                    .middle_of(ids.graphs[i])
+                    .color(color::DARK_CHARCOAL.alpha(0.9))
+                    .set(ids.error_panels[i], ui);
+                widget::Text::new(error)
+                    .mid_top_with_margin_on(ids.error_panels[i], 8.)
+                    .w(panel_w - 16.)
+                    .center_justify()
+                    .font_size(11)
+                    .color(ALERT_COLOR)
+                    .set(ids.error_texts[i], ui);
+                if let Some(host) = &target.host_edit {
+                    // Lets the user fix a typo in the host, applied with Enter
+                    for event in widget::TextBox::new(host)
+                        .mid_bottom_with_margin_on(ids.error_panels[i], 8.)
+                        .w_h(panel_w - 16., 24.)
+                        .font_size(11)
+                        .color(color::CHARCOAL)
+                        .text_color(color::LIGHT_GREY)
+                        .border_color(graph_color)
+                        .set(ids.host_boxes[i], ui)
+                    {
+                        host_events.push((i, event));
+                    }
+                }
+            }
+            if event.zoom != self.settings.zoom {
+                zoom = event.zoom;
+            }
+            drag += event.drag;
+            if ui.widget_input(ids.graphs[i]).mouse().is_some() {
+                self.current_target = i;
+            }
+        }
+        if zoom.1 != self.settings.zoom.1 && self.settings.y_fit != YFit::Manual {
+            // Zooming by hand takes over from the auto-fit
+            self.settings.y_fit = YFit::Manual;
+        }
+        self.settings.zoom = zoom;
+
+        /* STATUS BAR */
+        // Flashes twice a second while a target is being alerted on
+        let alerting = self.targets.iter().any(|t| t.alert.reason().is_some());
+        let period = ALERT_FLASH_PERIOD.as_nanos();
+        let flash = alerting
+            && SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .is_ok_and(|d| d.as_nanos() % (2 * period) < period);
+        widget::Rectangle::fill([ui.w_of(ids.canvas).unwrap_or(0.), STATUS_BAR_HEIGHT])
+            .mid_bottom_of(ids.canvas)
+            .color(if flash {
+                ALERT_FLASH_COLOR
+            } else {
+                color::CHARCOAL
+            })
+            .set(ids.status_bar, ui);
+        widget::Text::new(&status_text)
+            .mid_left_with_margin_on(ids.status_bar, 6.)
+            .font_size(11)
+            .color(color::LIGHT_GREY)
+            .set(ids.status_text, ui);
+
+        let mut settings_event = None;
+        if self.show_settings {
+            settings_event = Some(
+                SettingsPanel::new(&self.settings)
+                    .error(self.settings_error.as_deref())
+                    // Recordings list their targets once at the start
+                    .targets_editable(self.recorder.is_none() && self.replay_tx.is_none())
+                    .color(color::DARK_CHARCOAL)
+                    .text_color(color::LIGHT_GREY)
+                    .border_color(color::GREY)
+                    .middle_of(ids.canvas)
+                    .set(ids.settings_panel, ui),
+            );
+        }
 
         *needs_redraw = ui.has_changed();
+        drop(ui_cell);
+        for (i, event) in host_events {
+            match event {
+                widget::text_box::Event::Update(host) => self.targets[i].host_edit = Some(host),
+                widget::text_box::Event::Enter => self.set_host(i),
+            }
+        }
+        self.editing_settings = false;
+        if let Some(event) = settings_event {
+            self.editing_settings = event.editing;
+            for (field, value) in event.applied {
+                self.settings_error = self.apply_setting(field, value.trim()).err();
+                if self.settings_error.is_none() {
+                    self.save_settings();
+                }
+            }
+        }
+        if drag != 0. {
+            // Dragging the graphs to the right goes back in time
+            self.pan(-drag);
+        }
     }
 
     fn process_event(
@@ -305,18 +587,95 @@ impl LatGraphApp {
         }
 
         match event {
+            Event::UserEvent(event)
+                if event
+                    .target()
+                    .is_some_and(|id| self.target_index(id).is_none()) =>
+            {
+                // Sent by network threads that were stopped since, their target was removed or
+                // restarted
+            }
             Event::UserEvent(event) => {
                 debug!("Processing app event {:?}", event);
+                // Events of stopped targets were dropped above, the others aren't about a target
+                let index = event
+                    .target()
+                    .and_then(|id| self.target_index(id))
+                    .unwrap_or_default();
                 match event {
-                    AppEvent::Ping(time) => {
-                        self.ringbuf.sent(*time);
+                    AppEvent::Ping(_, time) => {
+                        let target = &mut self.targets[index];
+                        target.aggregates.sent(*time);
+                        target.total.sent();
+                        let ringbuf = &mut target.ringbuf;
+                        ringbuf.sent(*time);
+                        if let Some(recorder) = &mut self.recorder {
+                            recorder.sent(index, ringbuf.get_end_index() as u64, *time);
+                        }
+                        if let Some(metrics) = &self.metrics {
+                            metrics.sent(index, ringbuf.get_end_index() as u64, *time);
+                        }
+                        self.check_alerts(index);
+                    }
+                    AppEvent::Pong(_, id, time) => {
+                        if let Some(recorder) = &mut self.recorder {
+                            recorder.received(index, *id, *time);
+                        }
+                        if let Some(metrics) = &self.metrics {
+                            metrics.received(index, *id, *time);
+                        }
+                        let target = &mut self.targets[index];
+                        if let Some(lat) = target.ringbuf.received(*id, *time) {
+                            target.aggregates.received(*time - lat, lat);
+                            target.total.received(lat);
+                        }
+                        self.check_alerts(index);
                     }
-                    AppEvent::Pong(id, time) => {
-                        self.ringbuf.received(*id, *time);
+                    AppEvent::InvalidPacket(_) => {
+                        self.targets[index].invalid_packets += 1;
                     }
-                    AppEvent::Error(AppError::HostResolution) => {
-                        error!("Received a Host Resolution error, exiting");
-                        *should_exit = true;
+                    AppEvent::Error(_, AppError::HostResolution(e)) => {
+                        let host = &self.settings.targets[index].host;
+                        warn!("Couldn't resolve host {} ({}), retrying", host, e);
+                        let target = &mut self.targets[index];
+                        target.error = Some(format!(
+                            "Couldn't resolve {} ({}), retrying. Fix the host and press Enter:",
+                            host, e
+                        ));
+                        if target.host_edit.is_none() {
+                            target.host_edit = Some(host.clone());
+                        }
+                    }
+                    AppEvent::Error(_, AppError::Socket(e)) => {
+                        error!(
+                            "Target {} is disabled, its socket couldn't be opened ({})",
+                            index, e
+                        );
+                        self.targets[index].error =
+                            Some(format!("Disabled, couldn't open a socket ({})", e));
+                    }
+                    AppEvent::Connected(_) => {
+                        let target = &mut self.targets[index];
+                        target.error = None;
+                        target.host_edit = None;
+                    }
+                    AppEvent::ReplayStatus(position, paused) => {
+                        if let (Some(start), false) = (self.replay_start, paused) {
+                            // Pings are replayed as if sent now, date them from the recording
+                            let now = Instant::now();
+                            for target in &mut self.targets {
+                                target.ringbuf.set_anchor(now, start + *position);
+                            }
+                        }
+                        self.replay_position = Some((*position, *paused));
+                        self.update_title();
+                    }
+                    AppEvent::ReplayReset => {
+                        for target in &mut self.targets {
+                            target.ringbuf = RingBuffer::new(target.ringbuf.capacity());
+                            target.aggregates = Aggregates::new();
+                            target.total = Stats::default();
+                        }
                     }
                 }
                 *should_update_ui = true;
@@ -325,6 +684,20 @@ impl LatGraphApp {
                 WindowEvent::CloseRequested => {
                     *should_exit = true;
                 }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::Escape),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    self.show_settings = false;
+                }
+                WindowEvent::KeyboardInput { .. } if self.is_editing_text() => {
+                    // Keys go to the text box, not the shortcuts
+                }
                 WindowEvent::KeyboardInput {
                     input:
                         KeyboardInput {
@@ -334,7 +707,201 @@ impl LatGraphApp {
                         },
                     ..
                 } => {
-                    self.toggle_running();
+                    if let Some(replay_tx) = &self.replay_tx {
+                        let _ = replay_tx.send(ReplayCommand::TogglePause);
+                    } else {
+                        self.toggle_running();
+                    }
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode:
+                                Some(key @ (VirtualKeyCode::Left | VirtualKeyCode::Right)),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => match (&self.replay_tx, self.view_end) {
+                    (Some(replay_tx), None) => {
+                        let _ = replay_tx.send(match key {
+                            VirtualKeyCode::Left => ReplayCommand::Rewind(REPLAY_SEEK_STEP),
+                            _ => ReplayCommand::Forward(REPLAY_SEEK_STEP),
+                        });
+                    }
+                    _ => {
+                        let step = self.ui.w_of(self.widget_ids.canvas).unwrap_or(0.) * PAN_STEP;
+                        self.pan(match key {
+                            VirtualKeyCode::Left => -step,
+                            _ => step,
+                        });
+                    }
+                },
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::F),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    // Pings keep coming in while frozen, they just aren't scrolled into view
+                    self.view_end = match self.view_end {
+                        Some(_) => None,
+                        None => Some(self.live_end()),
+                    };
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::End),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    self.view_end = None;
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::E),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    self.export();
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::C),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    self.settings.clock_axis = !self.settings.clock_axis;
+                    self.save_settings();
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::G),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    self.settings.percentile_lines = !self.settings.percentile_lines;
+                    self.save_settings();
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::Y),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    self.settings.y_scale = self.settings.y_scale.next();
+                    info!("Vertical scale set to {:?}", self.settings.y_scale);
+                    self.save_settings();
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::A),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    self.settings.y_fit = self.settings.y_fit.next();
+                    info!("Vertical auto-fit set to {:?}", self.settings.y_fit);
+                    self.save_settings();
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::S),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    self.settings.plot_style = self.settings.plot_style.next();
+                    info!("Plot style set to {:?}", self.settings.plot_style);
+                    self.save_settings();
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::D),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    self.settings.histogram = !self.settings.histogram;
+                    self.save_settings();
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::B),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    self.settings.threshold_colors = !self.settings.threshold_colors;
+                    self.save_settings();
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::H),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    let factor = if self.modifiers.shift() { 0.5 } else { 2. };
+                    self.settings.history = self.settings.history.scale(factor);
+                    info!("History length set to {:?}", self.settings.history);
+                    self.resize_history();
+                    self.save_settings();
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::O),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    self.show_settings = !self.show_settings;
+                    self.settings_error = None;
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::Tab),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    // Cycle through the targets shown in the status bar
+                    self.current_target = (self.current_target + 1) % self.targets.len().max(1);
+                }
+                WindowEvent::ModifiersChanged(modifiers) => {
+                    self.modifiers = *modifiers;
                 }
                 WindowEvent::CursorLeft { .. } => {
                     self.is_mouse_over_window = false;
@@ -364,7 +931,358 @@ impl LatGraphApp {
     }
 
     fn send_settings(&self) {
-        self.settings_tx.send(self.settings.clone()).unwrap();
+        for (i, target) in self.targets.iter().enumerate() {
+            // The network threads of a target exit if its socket couldn't be opened
+            if target
+                .settings_tx
+                .send(self.settings.for_target(i))
+                .is_err()
+            {
+                debug!("Couldn't send settings to a stopped target");
+            }
+        }
+    }
+
+    /// Exports the history of all targets, as JSON if Shift is held and no path was given
+    fn export(&self) {
+        let path = self.export_path.clone().unwrap_or_else(|| {
+            if self.modifiers.shift() {
+                ExportFormat::Json.default_path()
+            } else {
+                ExportFormat::Csv.default_path()
+            }
+        });
+        let targets: Vec<_> = self
+            .targets
+            .iter()
+            .zip(self.settings.targets.iter())
+            .map(|(target, settings)| (settings.display_label(), &target.ringbuf))
+            .collect();
+        if let Err(e) = export::export(&path, ExportFormat::from_path(&path), &targets) {
+            error!("Couldn't export history to {:?}: {}", path, e);
+        }
+    }
+
+    /// Summary of the current target's state and statistics
+    fn status_text(&self) -> String {
+        let mut text = if self.settings.running {
+            format!("Running, every {:?}", self.settings.delay)
+        } else {
+            String::from("Paused")
+        };
+        if self.view_end.is_some() {
+            text += ", view frozen (End to go live)";
+        }
+        if let (Some(target), Some(target_settings)) = (
+            self.targets.get(self.current_target),
+            self.settings.targets.get(self.current_target),
+        ) {
+            let window = Stats::from_buffer(&target.ringbuf, Instant::now());
+            // Pings still awaiting a response don't count as lost yet
+            let pending = target.ringbuf.len() as u64 - window.sent;
+            let total_sent = target.total.sent.saturating_sub(pending);
+            let total_loss = if total_sent > 0 {
+                total_sent.saturating_sub(target.total.received) as f64 / total_sent as f64
+            } else {
+                0.
+            };
+            text = format!(
+                "{}  |  {}  |  loss {:.1}% (history), {:.1}% (total)  |  jitter {:.3}ms  |  {} sent, {} received",
+                target_settings.display_label(),
+                text,
+                window.loss() * 100.,
+                total_loss * 100.,
+                target.total.jitter.as_secs_f64() * 1000.,
+                target.total.sent,
+                target.total.received
+            );
+            if self.targets.len() > 1 {
+                text = format!(
+                    "[{}/{}] {}",
+                    self.current_target + 1,
+                    self.targets.len(),
+                    text
+                );
+            }
+        }
+        // Alerts come first, whichever target is current
+        let alert = self
+            .targets
+            .iter()
+            .zip(&self.settings.targets)
+            .find_map(|(target, settings)| Some((settings, target.alert.reason()?)));
+        if let Some((target_settings, reason)) = alert {
+            text = format!(
+                "ALERT {}: {}  |  {}",
+                target_settings.display_label(),
+                reason,
+                text
+            );
+        }
+        text
+    }
+
+    /// When the status bar flashes on or off next, if a target is being alerted on
+    fn next_flash(&self) -> Option<Instant> {
+        if self.targets.iter().all(|t| t.alert.reason().is_none()) {
+            return None;
+        }
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+        let elapsed = since_epoch.as_nanos() % ALERT_FLASH_PERIOD.as_nanos();
+        Some(Instant::now() + ALERT_FLASH_PERIOD - Duration::from_nanos(elapsed as u64))
+    }
+
+    /// Checks the alert rules against the latest pings of a target, signalling alerts as they're
+    /// raised or cleared
+    fn check_alerts(&mut self, index: usize) {
+        let target = &mut self.targets[index];
+        if !target
+            .alert
+            .update(&self.settings.alerts, &target.ringbuf, Instant::now())
+        {
+            return;
+        }
+        let label = self.settings.targets[index].display_label();
+        match target.alert.reason() {
+            Some(reason) => warn!("Alert on {}: {}", label, reason),
+            None => info!("Alert on {} cleared", label),
+        }
+        if !self.settings.alert_command.is_empty() {
+            alert::run_hook(&self.settings.alert_command, label, target.alert.reason());
+        }
+        self.update_title();
+    }
+
+    /// Sets the window title from the replay position and the targets being alerted on
+    fn update_title(&self) {
+        let mut title = String::from("Latency Graph");
+        if let Some((position, paused)) = self.replay_position {
+            let secs = position.as_secs();
+            title += &format!(
+                " - Replay {:02}:{:02}:{:02}{}",
+                secs / 3600,
+                secs / 60 % 60,
+                secs % 60,
+                if paused { " (paused)" } else { "" }
+            );
+        }
+        let alerts: Vec<&str> = self
+            .targets
+            .iter()
+            .zip(&self.settings.targets)
+            .filter(|(target, _)| target.alert.reason().is_some())
+            .map(|(_, settings)| settings.display_label())
+            .collect();
+        if !alerts.is_empty() {
+            title = format!("ALERT {} - {}", alerts.join(", "), title);
+        }
+        self.display.gl_window().window().set_title(&title);
+    }
+
+    /// Time at the right edge of the graphs when they follow the latest pings
+    fn live_end(&self) -> Instant {
+        let now = Instant::now();
+        if self.settings.running {
+            return now;
+        }
+        // Stopped graphs end after the last ping sent
+        self.targets
+            .iter()
+            .filter(|t| t.ringbuf.len() > 0)
+            .map(|t| t.ringbuf[t.ringbuf.get_end_index()].sent_time() + self.settings.delay)
+            .max()
+            .unwrap_or(now)
+    }
+
+    /// Moves the view by the given number of pixels, forward in time if positive. The view is
+    /// frozen if it was following the latest pings, and follows them again once it catches up.
+    fn pan(&mut self, pixels: f64) {
+        let live_end = self.live_end();
+        let view_end = self.view_end.unwrap_or(live_end);
+        let by = pixels_to_duration(self.settings.zoom, self.settings.delay, pixels);
+        self.view_end = if pixels > 0. {
+            Some(view_end + by).filter(|&end| end < live_end)
+        } else {
+            Some(view_end.checked_sub(by).unwrap_or(view_end))
+        };
+    }
+
+    /// Resizes the history of every target to match the settings, keeping the latest pings
+    fn resize_history(&mut self) {
+        let capacity = self.settings.history_capacity();
+        for target in &mut self.targets {
+            target.ringbuf.resize(capacity);
+        }
+    }
+
+    /// Starts the network threads of the targets in the settings, with an empty history
+    fn start_probes(&mut self) {
+        for i in 0..self.settings.targets.len() {
+            let target = self.start_target(self.settings.targets[i].probe);
+            self.targets.push(target);
+        }
+    }
+
+    /// Starts the network threads of a target under a new id, returning its empty state. Its
+    /// settings are sent along with the others by `send_settings`.
+    fn start_target(&mut self, probe: ProbeKind) -> Target {
+        let id = TargetId(self.next_target_id);
+        self.next_target_id += 1;
+        // When replaying, the settings are sent to nowhere
+        let (settings_tx, settings_rx) = mpsc::channel();
+        if self.replay_tx.is_none() {
+            probe::start(id, probe, settings_rx, self.event_proxy.clone());
+        }
+        Target {
+            id,
+            ringbuf: RingBuffer::new(self.settings.history_capacity()),
+            aggregates: Aggregates::new(),
+            total: Stats::default(),
+            settings_tx,
+            invalid_packets: 0,
+            alert: AlertState::new(Instant::now()),
+            error: None,
+            host_edit: None,
+        }
+    }
+
+    /// Current index of the target with the given id, `None` if it was removed or restarted
+    fn target_index(&self, id: TargetId) -> Option<usize> {
+        self.targets.iter().position(|t| t.id == id)
+    }
+
+    /// Checks a value entered in the settings panel and applies it
+    fn apply_setting(&mut self, field: Field, value: &str) -> Result<(), String> {
+        match field {
+            Field::Rate => {
+                let delay = match value.parse::<u64>().map(Duration::from_millis) {
+                    Ok(delay) if delay > Duration::ZERO && delay <= MAX_DELAY => delay,
+                    _ => {
+                        return Err(format!(
+                            "Invalid rate '{}', expected milliseconds up to {}",
+                            value,
+                            MAX_DELAY.as_millis()
+                        ))
+                    }
+                };
+                self.settings.delay = delay;
+                // A history given as a duration covers a different number of pings
+                self.resize_history();
+            }
+            Field::PayloadSize => {
+                let range = packet::HEADER_LEN..=packet::MAX_PAYLOAD_SIZE;
+                self.settings.payload_size = match value.parse::<usize>() {
+                    Ok(size) if range.contains(&size) => size,
+                    _ => {
+                        return Err(format!(
+                            "Invalid payload size '{}', expected {} to {} bytes",
+                            value,
+                            range.start(),
+                            range.end()
+                        ))
+                    }
+                };
+            }
+            Field::History => {
+                self.settings.history = HistoryLength::parse(value)
+                    .map_err(|e| format!("Invalid history '{}': {}", value, e))?;
+                self.resize_history();
+            }
+            Field::Target(index) if value.is_empty() => {
+                if index < self.targets.len() {
+                    // Closing its settings channel stops the network threads of the target
+                    info!("Removing target {}", index);
+                    self.settings.targets.remove(index);
+                    self.targets.remove(index);
+                    if let Some(metrics) = &self.metrics {
+                        metrics.remove(index);
+                    }
+                    if self.current_target >= index && self.current_target > 0 {
+                        self.current_target -= 1;
+                    }
+                }
+            }
+            Field::Target(index) => {
+                let mut target = TargetSettings::parse(value)
+                    .map_err(|e| format!("Invalid host '{}': {}", value, e))?;
+                match self.settings.targets.get_mut(index) {
+                    Some(old) if old.probe == target.probe => {
+                        // The network threads keep running and pick up the new host, so do the
+                        // counters
+                        target.color = old.color;
+                        target.thresholds = std::mem::take(&mut old.thresholds);
+                        *old = target;
+                        if let Some(metrics) = &self.metrics {
+                            metrics.relabel(&self.settings.targets);
+                        }
+                    }
+                    Some(old) => {
+                        info!("Probe of target {} changed, restarting it", index);
+                        target.color = old.color;
+                        target.thresholds = std::mem::take(&mut old.thresholds);
+                        *old = target;
+                        self.targets[index] = self.start_target(self.settings.targets[index].probe);
+                        if let Some(metrics) = &self.metrics {
+                            metrics.restart(index, &self.settings.targets[index]);
+                        }
+                    }
+                    None => {
+                        info!("Adding target {}", target.host);
+                        let new = self.start_target(target.probe);
+                        if let Some(metrics) = &self.metrics {
+                            metrics.push(&target);
+                        }
+                        self.settings.targets.push(target);
+                        self.targets.push(new);
+                    }
+                }
+            }
+        }
+        if let Field::Target(_) = field {
+            self.settings.running &= self.settings.has_hosts();
+            self.update_title();
+        }
+        self.send_settings();
+        Ok(())
+    }
+
+    /// Replaces the host of a target with the one typed in over its graph
+    fn set_host(&mut self, index: usize) {
+        let host = match &self.targets[index].host_edit {
+            Some(host) if !host.trim().is_empty() => String::from(host.trim()),
+            _ => return,
+        };
+        if host == self.settings.targets[index].host {
+            return;
+        }
+        info!("Changing host of target {} to {}", index, host);
+        self.targets[index].error = Some(format!("Resolving {}...", host));
+        self.settings.targets[index].host = host;
+        if let Some(metrics) = &self.metrics {
+            metrics.relabel(&self.settings.targets);
+        }
+        self.send_settings();
+        self.save_settings();
+    }
+
+    /// Whether a text box has the keyboard focus, in which case keys aren't shortcuts
+    fn is_editing_text(&self) -> bool {
+        (self.show_settings && self.editing_settings)
+            || self
+                .ui
+                .global_input()
+                .current
+                .widget_capturing_keyboard
+                .is_some_and(|id| self.widget_ids.host_boxes.contains(&id))
+    }
+
+    /// Writes the settings to the config file, if there's one
+    fn save_settings(&self) {
+        if let Some(path) = &self.config_path {
+            if let Err(err) = self.settings.save(path) {
+                error!("Couldn't save settings: {}", err);
+            }
+        }
     }
 
     fn toggle_running(&mut self) {
@@ -372,12 +1290,18 @@ impl LatGraphApp {
     }
 
     fn set_running(&mut self, running: bool) {
-        if running != self.settings.running && (!running || !self.settings.remote_host.is_empty()) {
+        if running != self.settings.running && (!running || self.settings.has_hosts()) {
             info!(
                 "Toggling packet sending {}",
                 if running { "ON" } else { "OFF" }
             );
             self.settings.running = running;
+            if running {
+                let now = Instant::now();
+                for target in &mut self.targets {
+                    target.alert.resume(now);
+                }
+            }
             self.send_settings();
         }
     }
@@ -390,6 +1314,7 @@ impl LatGraphApp {
         let redraw_delay = std::time::Duration::from_millis(16);
         // let redraw_delay = std::time::Duration::from_millis(16);
         let mut next_update = None;
+        let mut next_alert_check = Instant::now();
         let mut ui_update_needed = false;
         self.send_settings(); // Send initial settings to start the send thread
         event_loop.run(move |event, _, control_flow| {
@@ -403,12 +1328,22 @@ impl LatGraphApp {
                     return;
                 }
             }
+            // Rules are also checked when pings are sent and received, this catches silences such
+            // as while a host can't be resolved
+            if Instant::now() >= next_alert_check {
+                next_alert_check = Instant::now() + alert::CHECK_INTERVAL;
+                if self.settings.running && self.replay_start.is_none() {
+                    for index in 0..self.targets.len() {
+                        self.check_alerts(index);
+                    }
+                }
+            }
             // We don't want to draw any faster than 60 FPS, so set the UI only on every 16ms, unless:
             // - this is the very first event, or
             // - we didn't request update on the last event and new events have arrived since then.
             let should_set_ui_on_main_events_cleared = next_update.is_none() && ui_update_needed;
             match (&event, should_set_ui_on_main_events_cleared) {
-                (Event::NewEvents(StartCause::Init { .. }), _)
+                (Event::NewEvents(StartCause::Init), _)
                 | (Event::NewEvents(StartCause::ResumeTimeReached { .. }), _)
                 | (Event::MainEventsCleared, true) => {
                     trace!(
@@ -430,23 +1365,47 @@ impl LatGraphApp {
                 }
                 _ => {}
             }
-            if let Some(next_update) = next_update {
-                *control_flow = ControlFlow::WaitUntil(next_update);
+            let mut wake_at = next_update;
+            if !self.settings.alerts.is_empty() {
+                wake_at = Some(wake_at.map_or(next_alert_check, |t| t.min(next_alert_check)));
+            }
+            // Only the status bar changes when it flashes, so the UI is set again just in time
+            // rather than redrawn continuously
+            if let Some(flash_at) = self.next_flash() {
+                wake_at = Some(wake_at.map_or(flash_at, |t| t.min(flash_at)));
+            }
+            if let Some(wake_at) = wake_at {
+                *control_flow = ControlFlow::WaitUntil(wake_at);
             } else {
                 *control_flow = ControlFlow::Wait;
             }
             // Request redraw if needed.
-            match &event {
-                Event::RedrawRequested(_) => {
-                    self.redraw();
-                }
-                _ => {}
+            if let Event::RedrawRequested(_) = &event {
+                self.redraw();
             }
         })
     }
 }
 
 impl LatGraphSettings {
+    /// Number of pings to keep per target to cover the history length
+    pub fn history_capacity(&self) -> usize {
+        self.history.capacity(self.delay)
+    }
+
+    /// Whether at least one target has a host to send pings to
+    pub fn has_hosts(&self) -> bool {
+        self.targets.iter().any(|t| !t.host.is_empty())
+    }
+
+    /// Settings sent to the network threads of a target, which only see their own target
+    pub fn for_target(&self, index: usize) -> LatGraphSettings {
+        LatGraphSettings {
+            targets: self.targets[index..=index].to_vec(),
+            ..self.clone()
+        }
+    }
+
     #[cfg(not(feature = "config"))]
     pub fn save(&self, _: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
         Ok(())
@@ -469,7 +1428,7 @@ impl LatGraphSettings {
             .truncate(true)
             .open(path)?;
 
-        file.write(ser.as_bytes())?;
+        file.write_all(ser.as_bytes())?;
         file.flush()?;
 
         Ok(())
@@ -479,12 +1438,399 @@ impl LatGraphSettings {
 impl Default for LatGraphSettings {
     fn default() -> Self {
         LatGraphSettings {
-            remote_host: String::new(),
             delay: Duration::from_millis(100),
             running: false,
             zoom: (crate::widget::ZOOM_DEFAULT, crate::widget::ZOOM_DEFAULT),
+            payload_size: packet::HEADER_LEN,
+            clock_axis: false,
+            percentiles: vec![50., 95., 99.],
+            percentile_lines: false,
+            y_scale: YScale::default(),
+            y_fit: YFit::default(),
+            plot_style: PlotStyle::default(),
+            histogram: false,
+            threshold_colors: false,
+            alert_command: String::new(),
+            history: HistoryLength::default(),
+            targets: Vec::new(),
+            thresholds: Vec::new(),
+            alerts: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for HistoryLength {
+    /// Formats the length as given on the command line
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            HistoryLength::Count(count) => write!(f, "{}", count),
+            HistoryLength::Duration(duration) => {
+                let secs = duration.as_secs_f64();
+                if secs >= 3600. && secs % 3600. == 0. {
+                    write!(f, "{}h", secs / 3600.)
+                } else if secs >= 60. && secs % 60. == 0. {
+                    write!(f, "{}m", secs / 60.)
+                } else {
+                    write!(f, "{}s", secs)
+                }
+            }
+        }
+    }
+}
+
+impl Default for HistoryLength {
+    fn default() -> Self {
+        HistoryLength::Count(1000)
+    }
+}
+
+impl HistoryLength {
+    /// Parses a history length from the command line, as a number of pings or as a duration in
+    /// seconds, minutes or hours (e.g `5000`, `90s`, `10m`, `2h`)
+    pub fn parse(arg: &str) -> Result<Self, String> {
+        let unit = match arg.chars().last() {
+            Some('s') => 1.,
+            Some('m') => 60.,
+            Some('h') => 3600.,
+            _ => {
+                return match arg.parse() {
+                    Ok(0) | Err(_) => Err(String::from("expected a number of pings or a duration")),
+                    Ok(count) => Ok(HistoryLength::Count(count)),
+                }
+            }
+        };
+        match arg[..arg.len() - 1].parse::<f64>() {
+            Ok(n) if n > 0. => match Duration::try_from_secs_f64(n * unit) {
+                Ok(duration)
+                    if duration >= MIN_HISTORY_DURATION && duration <= MAX_HISTORY_DURATION =>
+                {
+                    Ok(HistoryLength::Duration(duration))
+                }
+                _ => Err(String::from("expected a duration between 1s and 8760h")),
+            },
+            _ => Err(String::from("expected a number of pings or a duration")),
+        }
+    }
+
+    /// Multiplies the length by the given factor, keeping at least one ping and at most a year
+    pub fn scale(self, factor: f64) -> Self {
+        match self {
+            HistoryLength::Count(count) => HistoryLength::Count(
+                ((count as f64 * factor).round() as usize).clamp(1, MAX_HISTORY),
+            ),
+            HistoryLength::Duration(duration) => HistoryLength::Duration(
+                Duration::try_from_secs_f64(duration.as_secs_f64() * factor)
+                    .unwrap_or(MAX_HISTORY_DURATION)
+                    .clamp(MIN_HISTORY_DURATION, MAX_HISTORY_DURATION),
+            ),
+        }
+    }
+
+    /// Number of pings sent at the given interval needed to cover this length
+    pub fn capacity(self, delay: Duration) -> usize {
+        let count = match self {
+            HistoryLength::Count(count) => count,
+            HistoryLength::Duration(duration) => {
+                (duration.as_secs_f64() / delay.as_secs_f64().max(0.001)).ceil() as usize
+            }
+        };
+        count.clamp(1, MAX_HISTORY)
+    }
+}
+
+impl fmt::Display for TargetId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for TargetSettings {
+    /// Formats the target as given on the command line
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.label.is_empty() {
+            write!(f, "{}=", self.label)?;
+        }
+        if self.probe != ProbeKind::default() {
+            write!(f, "{}://", self.probe.scheme())?;
+        }
+        write!(f, "{}", self.host)
+    }
+}
+
+impl TargetSettings {
+    /// Parses a target from the command line, in the form `[label=][probe://]host[:port]` where
+    /// probe is either `udp` (the default), `icmp` or `tcp`
+    pub fn parse(arg: &str) -> Result<Self, String> {
+        let (label, host) = match arg.find('=') {
+            Some(i) => (&arg[..i], &arg[i + 1..]),
+            None => ("", arg),
+        };
+        let (probe, host) = match host.find("://") {
+            Some(i) => match ProbeKind::from_scheme(&host[..i]) {
+                Some(probe) => (probe, &host[i + 3..]),
+                None => return Err(format!("Unknown probe type '{}'", &host[..i])),
+            },
+            None => (ProbeKind::default(), host),
+        };
+        Ok(TargetSettings {
+            host: String::from(host),
+            label: String::from(label),
+            color: None,
+            probe,
+            thresholds: Vec::new(),
+        })
+    }
+
+    pub fn display_label(&self) -> &str {
+        if self.label.is_empty() {
+            &self.host
+        } else {
+            &self.label
+        }
+    }
+
+    /// Color of the graph for the target at the given index
+    pub fn color(&self, index: usize) -> color::Color {
+        match self.color {
+            Some([r, g, b]) => color::rgb_bytes(r, g, b),
+            None => TARGET_COLORS[index % TARGET_COLORS.len()],
+        }
+    }
+
+    /// Thresholds marked on the target's graph, the given global ones if it has none
+    pub fn thresholds<'a>(&'a self, global: &'a [Threshold]) -> &'a [Threshold] {
+        if self.thresholds.is_empty() {
+            global
+        } else {
+            &self.thresholds
+        }
+    }
+}
+
+impl Threshold {
+    /// Parses comma-separated thresholds from the command line, each as `<ms>:<color>` in
+    /// increasing order, optionally followed by the color of the latencies above them (e.g
+    /// `30:green,80:yellow,red`). Colors are given by name or as `#rrggbb`.
+    pub fn parse_list(arg: &str) -> Result<Vec<Self>, String> {
+        let mut thresholds: Vec<Threshold> = Vec::new();
+        for part in arg.split(',').filter(|p| !p.is_empty()) {
+            let (below, color) = match part.find(':') {
+                Some(i) => match part[..i].trim().parse::<f64>() {
+                    Ok(ms) if ms > 0. && ms.is_finite() => (Some(ms), &part[i + 1..]),
+                    _ => return Err(format!("invalid latency '{}'", &part[..i])),
+                },
+                None => (None, part),
+            };
+            let last = thresholds.last().map(|t| t.below);
+            match (last, below) {
+                (Some(None), _) => {
+                    return Err(String::from(
+                        "only the last threshold can be without a latency",
+                    ))
+                }
+                (Some(Some(last)), Some(below)) if below <= last => {
+                    return Err(String::from("thresholds must be in increasing order"))
+                }
+                _ => {}
+            }
+            let color = parse_color(color.trim())
+                .ok_or_else(|| format!("unknown color '{}'", color.trim()))?;
+            thresholds.push(Threshold { below, color });
+        }
+        Ok(thresholds)
+    }
+
+    pub fn color(&self) -> color::Color {
+        let [r, g, b] = self.color;
+        color::rgb_bytes(r, g, b)
+    }
+}
+
+/// Parses a color name from `COLOR_NAMES` or an hexadecimal `#rrggbb` color
+fn parse_color(name: &str) -> Option<[u8; 3]> {
+    if let Some(hex) = name.strip_prefix('#') {
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
         }
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        return Some([(value >> 16) as u8, (value >> 8) as u8, value as u8]);
     }
+    COLOR_NAMES
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|&(_, color)| color)
 }
 
 conrod_winit::v023_conversion_fns!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_target() {
+        let target = TargetSettings::parse("example.com").unwrap();
+        assert_eq!(target.host, "example.com");
+        assert_eq!(target.label, "");
+        assert_eq!(target.probe, ProbeKind::UdpEcho);
+
+        let target = TargetSettings::parse("router=icmp://192.168.1.1").unwrap();
+        assert_eq!(target.host, "192.168.1.1");
+        assert_eq!(target.label, "router");
+        assert_eq!(target.probe, ProbeKind::Icmp);
+
+        let target = TargetSettings::parse("tcp://example.com:443").unwrap();
+        assert_eq!(target.host, "example.com:443");
+        assert_eq!(target.probe, ProbeKind::Tcp);
+        assert_eq!(target.display_label(), "example.com:443");
+
+        let target = TargetSettings::parse("udp://[::1]:7").unwrap();
+        assert_eq!(target.host, "[::1]:7");
+        assert_eq!(target.probe, ProbeKind::UdpEcho);
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn load_remote_host() {
+        // Saved before multiple targets could be monitored
+        let settings: LatGraphSettings = toml::from_str("remote_host = \"host:7\"").unwrap();
+        assert_eq!(settings.targets.len(), 1);
+        assert_eq!(settings.targets[0].host, "host:7");
+        assert_eq!(settings.targets[0].probe, ProbeKind::UdpEcho);
+
+        let settings: LatGraphSettings = toml::from_str("remote_host = \"\"").unwrap();
+        assert!(settings.targets.is_empty());
+
+        let settings: LatGraphSettings =
+            toml::from_str("[[targets]]\nhost = \"a\"\n[[targets]]\nhost = \"b\"").unwrap();
+        let hosts: Vec<_> = settings.targets.iter().map(|t| t.host.as_str()).collect();
+        assert_eq!(hosts, ["a", "b"]);
+    }
+
+    #[test]
+    fn parse_target_invalid() {
+        assert!(TargetSettings::parse("http://example.com").is_err());
+        assert!(TargetSettings::parse("name=ftp://example.com").is_err());
+    }
+
+    #[test]
+    fn target_display_round_trip() {
+        for arg in [
+            "example.com",
+            "dns=icmp://1.1.1.1",
+            "web=tcp://example.com:443",
+        ] {
+            let target = TargetSettings::parse(arg).unwrap();
+            assert_eq!(target.to_string(), arg);
+        }
+        // UDP Echo is the default, its scheme is left out
+        let target = TargetSettings::parse("udp://example.com").unwrap();
+        assert_eq!(target.to_string(), "example.com");
+    }
+
+    #[test]
+    fn parse_colors() {
+        assert_eq!(parse_color("red"), Some([220, 50, 40]));
+        assert_eq!(parse_color("Green"), Some([80, 200, 80]));
+        assert_eq!(parse_color("#1a2B3c"), Some([0x1a, 0x2b, 0x3c]));
+        assert_eq!(parse_color("#000000"), Some([0, 0, 0]));
+        assert_eq!(parse_color("teal"), None);
+        assert_eq!(parse_color("#12345"), None);
+        assert_eq!(parse_color("#1234567"), None);
+        assert_eq!(parse_color("#12345g"), None);
+        assert_eq!(parse_color("#+12345"), None);
+        assert_eq!(parse_color(""), None);
+    }
+
+    #[test]
+    fn parse_history() {
+        assert_eq!(HistoryLength::parse("1000"), Ok(HistoryLength::Count(1000)));
+        assert_eq!(
+            HistoryLength::parse("90s"),
+            Ok(HistoryLength::Duration(Duration::from_secs(90)))
+        );
+        assert_eq!(
+            HistoryLength::parse("1.5m"),
+            Ok(HistoryLength::Duration(Duration::from_secs(90)))
+        );
+        assert_eq!(
+            HistoryLength::parse("2h"),
+            Ok(HistoryLength::Duration(Duration::from_secs(7200)))
+        );
+        for invalid in [
+            "", "0", "-5", "0s", "-1m", "s", "10d", "inf", "infh", "NaNs", "1e20s", "0.5s", "8761h",
+        ] {
+            assert!(HistoryLength::parse(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn scale_history() {
+        let history = HistoryLength::Duration(Duration::from_secs(60));
+        assert_eq!(
+            history.scale(2.),
+            HistoryLength::Duration(Duration::from_secs(120))
+        );
+        assert_eq!(
+            history.scale(1e30),
+            HistoryLength::Duration(MAX_HISTORY_DURATION)
+        );
+        assert_eq!(
+            history.scale(1e-30),
+            HistoryLength::Duration(MIN_HISTORY_DURATION)
+        );
+        assert_eq!(
+            HistoryLength::Count(10).scale(0.01),
+            HistoryLength::Count(1)
+        );
+        assert_eq!(
+            HistoryLength::Count(10).scale(1e30),
+            HistoryLength::Count(MAX_HISTORY)
+        );
+    }
+
+    #[test]
+    fn parse_thresholds() {
+        let thresholds = Threshold::parse_list("30:green, 80.5:#ff8000,red").unwrap();
+        assert_eq!(
+            thresholds,
+            vec![
+                Threshold {
+                    below: Some(30.),
+                    color: [80, 200, 80]
+                },
+                Threshold {
+                    below: Some(80.5),
+                    color: [0xff, 0x80, 0]
+                },
+                Threshold {
+                    below: None,
+                    color: [220, 50, 40]
+                },
+            ]
+        );
+        assert_eq!(Threshold::parse_list(""), Ok(Vec::new()));
+        assert_eq!(Threshold::parse_list("50:blue,").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn parse_thresholds_order() {
+        assert!(Threshold::parse_list("80:yellow,30:green").is_err());
+        // Duplicates
+        assert!(Threshold::parse_list("30:green,30:yellow").is_err());
+        assert!(Threshold::parse_list("red,green").is_err());
+        assert!(Threshold::parse_list("red,30:green").is_err());
+    }
+
+    #[test]
+    fn parse_thresholds_invalid() {
+        assert!(Threshold::parse_list("30:teal").is_err());
+        assert!(Threshold::parse_list("30:#12345").is_err());
+        assert!(Threshold::parse_list("30:").is_err());
+        assert!(Threshold::parse_list("0:green").is_err());
+        assert!(Threshold::parse_list("-5:green").is_err());
+        assert!(Threshold::parse_list("abc:green").is_err());
+        assert!(Threshold::parse_list("inf:green").is_err());
+        assert!(Threshold::parse_list("NaN:green").is_err());
+    }
+}
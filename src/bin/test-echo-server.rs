@@ -97,7 +97,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("Starting listen on {}", bind_sockaddr);
     let mut rng = thread_rng();
-    let mut buffer = [0u8; 64];
+    let mut buffer = vec![0u8; 65536];
     loop {
         match socket.recv_from(&mut buffer).await {
             Ok((len, addr)) => {
@@ -107,9 +107,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     let wait = next_latency() as u64;
                     trace!("Received {} bytes from {}, delaying {}ms", len, addr, wait);
                     let socket = socket.clone();
+                    let payload = buffer[..len].to_vec();
                     tokio::spawn(async move {
                         time::sleep(Duration::from_millis(wait)).await;
-                        socket.send_to(&buffer[..len], addr).await?;
+                        socket.send_to(&payload, addr).await?;
                         Ok(()) as Result<(), std::io::Error>
                     });
                 }
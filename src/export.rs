@@ -0,0 +1,191 @@
+use crate::{
+    ringbuf::{Ping, RingBuffer},
+    stats::LOSS_TIMEOUT,
+};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local, SecondsFormat};
+use log::*;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// A ping as written to the exported file
+struct Row {
+    seq: usize,
+    sent: DateTime<Local>,
+    latency: Option<Duration>,
+    lost: bool,
+}
+
+impl ExportFormat {
+    /// Guesses the format from the file's extension, defaulting to CSV
+    pub fn from_path(path: &Path) -> ExportFormat {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => ExportFormat::Json,
+            _ => ExportFormat::Csv,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    /// File name in the working directory for exports that weren't given a path
+    pub fn default_path(self) -> PathBuf {
+        PathBuf::from(format!(
+            "latgraph-{}.{}",
+            Local::now().format("%Y%m%d-%H%M%S"),
+            self.extension()
+        ))
+    }
+}
+
+/// Writes the history of each target, given with its label, to a file in the given format
+pub fn export(
+    path: &Path,
+    format: ExportFormat,
+    targets: &[(&str, &RingBuffer)],
+) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    match format {
+        ExportFormat::Csv => writeln!(out, "target,seq,sent,latency_ms,lost")?,
+        ExportFormat::Json => write!(out, "[")?,
+    }
+    let mut first = true;
+    for (label, ringbuf) in targets {
+        for row in rows(ringbuf) {
+            let sent = row.sent.to_rfc3339_opts(SecondsFormat::Millis, false);
+            let latency = row
+                .latency
+                .map(|l| format!("{:.3}", l.as_secs_f64() * 1000.));
+            match format {
+                ExportFormat::Csv => writeln!(
+                    out,
+                    "{},{},{},{},{}",
+                    csv_field(label),
+                    row.seq,
+                    sent,
+                    latency.unwrap_or_default(),
+                    row.lost
+                )?,
+                ExportFormat::Json => write!(
+                    out,
+                    "{}\n  {{\"target\": {}, \"seq\": {}, \"sent\": \"{}\", \"latency_ms\": {}, \"lost\": {}}}",
+                    if first { "" } else { "," },
+                    json_string(label),
+                    row.seq,
+                    sent,
+                    latency.as_deref().unwrap_or("null"),
+                    row.lost
+                )?,
+            }
+            first = false;
+        }
+    }
+    if format == ExportFormat::Json {
+        writeln!(out, "\n]")?;
+    }
+    out.flush()?;
+    info!("Exported history to {:?}", path);
+    Ok(())
+}
+
+fn rows(ringbuf: &RingBuffer) -> impl Iterator<Item = Row> + '_ {
+    let now = Instant::now();
+    ringbuf
+        .get_data()
+        .into_iter()
+        .enumerate()
+        .map(move |(i, ping)| {
+            let age = now.saturating_duration_since(ping.sent_time());
+            let sent = DateTime::from(ringbuf.wall_time(ping.sent_time()));
+            match ping {
+                Ping::Sent(_) => Row {
+                    seq: ringbuf.get_start_index() + i,
+                    sent,
+                    latency: None,
+                    lost: age >= LOSS_TIMEOUT,
+                },
+                Ping::Received(_, latency) => Row {
+                    seq: ringbuf.get_start_index() + i,
+                    sent,
+                    latency: Some(latency),
+                    lost: false,
+                },
+            }
+        })
+}
+
+/// Quotes a CSV field if needed
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        String::from(s)
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_escaping() {
+        assert_eq!(csv_field("home router"), "home router");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("the \"main\" one"), "\"the \"\"main\"\" one\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("cr\r"), "\"cr\r\"");
+        assert_eq!(csv_field(""), "");
+    }
+
+    #[test]
+    fn json_escaping() {
+        assert_eq!(json_string("home router"), "\"home router\"");
+        assert_eq!(json_string("the \"main\" one"), "\"the \\\"main\\\" one\"");
+        assert_eq!(json_string("back\\slash"), "\"back\\\\slash\"");
+        assert_eq!(json_string("two\nlines\t"), "\"two\\u000alines\\u0009\"");
+        assert_eq!(json_string("héllo ✓"), "\"héllo ✓\"");
+        assert_eq!(json_string(""), "\"\"");
+    }
+
+    #[test]
+    fn format_from_path() {
+        assert_eq!(
+            ExportFormat::from_path(Path::new("out.JSON")),
+            ExportFormat::Json
+        );
+        assert_eq!(
+            ExportFormat::from_path(Path::new("out.csv")),
+            ExportFormat::Csv
+        );
+        assert_eq!(ExportFormat::from_path(Path::new("out")), ExportFormat::Csv);
+    }
+}
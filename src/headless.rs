@@ -0,0 +1,297 @@
+use crate::{
+    alert::{self, AlertState},
+    app::{AppError, AppEvent, LatGraphSettings, TargetId},
+    export::{self, ExportFormat},
+    metrics::Metrics,
+    probe,
+    record::Recorder,
+    ringbuf::{Ping, RingBuffer},
+    stats::{Stats, LOSS_TIMEOUT},
+};
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use log::*;
+
+/// How often the main loop checks for the exit conditions while waiting for events
+const TICK: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Default)]
+pub struct HeadlessOptions {
+    /// Writes the pings and responses to a file as they happen
+    pub recorder: Option<Recorder>,
+    /// Counters served to Prometheus
+    pub metrics: Option<Metrics>,
+    /// File to which the history is exported before exiting
+    pub export_path: Option<PathBuf>,
+    /// Number of pings to send to each target before exiting
+    pub count: Option<u64>,
+    /// Time after which to stop sending pings and exit
+    pub duration: Option<Duration>,
+    /// Print a summary at this interval instead of a line for every response
+    pub summary_interval: Option<Duration>,
+}
+
+struct Target {
+    label: String,
+    ringbuf: RingBuffer,
+    settings_tx: mpsc::Sender<LatGraphSettings>,
+    total: Stats,
+    interval: Stats,
+    invalid_packets: u64,
+    alert: AlertState,
+    /// Whether we stopped sending pings to this target
+    done: bool,
+    /// Whether it was stopped because its host couldn't be resolved or its socket opened
+    failed: bool,
+}
+
+/// Runs the probes without a window, printing results to the standard output until the count or
+/// duration is reached or the process is interrupted. Returns whether any response was received.
+pub fn run(mut settings: LatGraphSettings, mut options: HeadlessOptions) -> bool {
+    let (event_tx, event_rx) = mpsc::channel();
+    let mut targets: Vec<Target> = settings
+        .targets
+        .iter()
+        .enumerate()
+        .map(|(i, target)| {
+            let (settings_tx, settings_rx) = mpsc::channel();
+            probe::start(TargetId(i), target.probe, settings_rx, event_tx.clone());
+            Target {
+                label: String::from(target.display_label()),
+                ringbuf: RingBuffer::new(settings.history_capacity()),
+                settings_tx,
+                total: Stats::default(),
+                interval: Stats::default(),
+                invalid_packets: 0,
+                alert: AlertState::new(Instant::now()),
+                done: false,
+                failed: false,
+            }
+        })
+        .collect();
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    {
+        let interrupted = interrupted.clone();
+        if let Err(e) = ctrlc::set_handler(move || interrupted.store(true, Ordering::Relaxed)) {
+            warn!("Couldn't set interrupt handler: {}", e);
+        }
+    }
+
+    settings.running = true;
+    for (i, target) in targets.iter().enumerate() {
+        let _ = target.settings_tx.send(settings.for_target(i));
+    }
+
+    let start = Instant::now();
+    let mut stop_at = options.duration.map(|d| start + d);
+    // Once we stop sending, time after which we stop waiting for the last responses
+    let mut drain_until = None;
+    let mut next_summary = options.summary_interval.map(|i| start + i);
+    let mut next_alert_check = start + alert::CHECK_INTERVAL;
+    while !interrupted.load(Ordering::Relaxed) {
+        match event_rx.recv_timeout(TICK) {
+            Ok(AppEvent::Ping(TargetId(i), time)) => {
+                let target = &mut targets[i];
+                if !target.done {
+                    target.ringbuf.sent(time);
+                    if let Some(recorder) = &mut options.recorder {
+                        recorder.sent(i, target.ringbuf.get_end_index() as u64, time);
+                    }
+                    if let Some(metrics) = &options.metrics {
+                        metrics.sent(i, target.ringbuf.get_end_index() as u64, time);
+                    }
+                    target.total.sent();
+                    target.interval.sent();
+                    if options.count == Some(target.total.sent) {
+                        stop_target(target, settings.for_target(i));
+                    }
+                    check_alerts(target, &settings);
+                }
+            }
+            Ok(AppEvent::Pong(TargetId(i), id, time)) => {
+                let target = &mut targets[i];
+                if let Some(recorder) = &mut options.recorder {
+                    recorder.received(i, id, time);
+                }
+                if let Some(metrics) = &options.metrics {
+                    metrics.received(i, id, time);
+                }
+                if let Some(lat) = target.ringbuf.received(id, time) {
+                    target.total.received(lat);
+                    target.interval.received(lat);
+                    if options.summary_interval.is_none() {
+                        println!("{}: seq={} time={}", target.label, id, format_ms(lat));
+                    }
+                }
+                check_alerts(target, &settings);
+            }
+            Ok(AppEvent::InvalidPacket(TargetId(i))) => targets[i].invalid_packets += 1,
+            Ok(AppEvent::Error(TargetId(i), e)) => {
+                let target = &mut targets[i];
+                match e {
+                    AppError::HostResolution(e) => {
+                        eprintln!("{}: couldn't resolve host ({})", target.label, e)
+                    }
+                    AppError::Socket(e) => {
+                        eprintln!("{}: couldn't open socket ({})", target.label, e)
+                    }
+                }
+                // Don't keep retrying in the background, nobody is there to fix the host
+                stop_target(target, settings.for_target(i));
+                target.failed = true;
+            }
+            Ok(AppEvent::Connected(_))
+            | Ok(AppEvent::ReplayStatus(..))
+            | Ok(AppEvent::ReplayReset) => {}
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+
+        let now = Instant::now();
+        if stop_at.is_some_and(|t| now >= t) {
+            stop_at = None;
+            for (i, target) in targets.iter_mut().enumerate().filter(|(_, t)| !t.done) {
+                stop_target(target, settings.for_target(i));
+            }
+        }
+        if drain_until.is_none() && targets.iter().all(|t| t.done) {
+            drain_until = Some(now + LOSS_TIMEOUT);
+        }
+        if let Some(drain_until) = drain_until {
+            let pending = targets
+                .iter()
+                .any(|t| t.ringbuf.iter().any(|p| matches!(p, Ping::Sent(_))));
+            if now >= drain_until || !pending {
+                break;
+            }
+        }
+        // Rules are also checked when pings are sent and received, this catches targets that
+        // went silent, e.g because their host couldn't be resolved
+        if now >= next_alert_check && drain_until.is_none() {
+            next_alert_check = now + alert::CHECK_INTERVAL;
+            for target in targets.iter_mut().filter(|t| !t.done || t.failed) {
+                check_alerts(target, &settings);
+            }
+        }
+        if let (Some(next), Some(interval)) = (next_summary, options.summary_interval) {
+            if now >= next && drain_until.is_none() {
+                for target in targets.iter_mut() {
+                    print_interval(target);
+                    target.interval = Stats::default();
+                }
+                next_summary = Some(next + interval);
+            }
+        }
+    }
+
+    let elapsed = start.elapsed();
+    for target in &targets {
+        print_summary(target, elapsed);
+    }
+    if let Some(path) = &options.export_path {
+        let history: Vec<_> = targets
+            .iter()
+            .map(|t| (t.label.as_str(), &t.ringbuf))
+            .collect();
+        if let Err(e) = export::export(path, ExportFormat::from_path(path), &history) {
+            eprintln!("Couldn't export history to {:?}: {}", path, e);
+        }
+    }
+    targets.iter().any(|t| t.total.received > 0)
+}
+
+/// Stops sending pings to the target, given the settings of its network threads
+fn stop_target(target: &mut Target, settings: LatGraphSettings) {
+    target.done = true;
+    let _ = target.settings_tx.send(LatGraphSettings {
+        running: false,
+        ..settings
+    });
+}
+
+/// Prints alerts on the target as they're raised or cleared, running the alert command if any
+fn check_alerts(target: &mut Target, settings: &LatGraphSettings) {
+    if !target
+        .alert
+        .update(&settings.alerts, &target.ringbuf, Instant::now())
+    {
+        return;
+    }
+    match target.alert.reason() {
+        Some(reason) => println!("{}: ALERT {}", target.label, reason),
+        None => println!("{}: alert cleared", target.label),
+    }
+    if !settings.alert_command.is_empty() {
+        alert::run_hook(
+            &settings.alert_command,
+            &target.label,
+            target.alert.reason(),
+        );
+    }
+}
+
+fn print_interval(target: &Target) {
+    let stats = &target.interval;
+    print!(
+        "{}: {} sent, {} received, {:.1}% loss",
+        target.label,
+        stats.sent,
+        stats.received,
+        stats.loss() * 100.
+    );
+    if let (Some(min), Some(avg), Some(max)) = (stats.min, stats.avg(), stats.max) {
+        print!(
+            ", min/avg/max/jitter = {}",
+            format_rtt(min, avg, max, stats.jitter)
+        );
+    }
+    println!();
+}
+
+fn print_summary(target: &Target, elapsed: Duration) {
+    let stats = &target.total;
+    println!();
+    println!("--- {} statistics ---", target.label);
+    print!(
+        "{} packets transmitted, {} received, {:.1}% packet loss",
+        stats.sent,
+        stats.received,
+        stats.loss() * 100.
+    );
+    if target.invalid_packets > 0 {
+        print!(", {} invalid", target.invalid_packets);
+    }
+    println!(", time {:.1}s", elapsed.as_secs_f64());
+    if let (Some(min), Some(avg), Some(max)) = (stats.min, stats.avg(), stats.max) {
+        println!(
+            "rtt min/avg/max/jitter = {}",
+            format_rtt(min, avg, max, stats.jitter)
+        );
+    }
+}
+
+fn format_rtt(min: Duration, avg: Duration, max: Duration, jitter: Duration) -> String {
+    format!(
+        "{:.3}/{:.3}/{:.3}/{:.3} ms",
+        as_ms(min),
+        as_ms(avg),
+        as_ms(max),
+        as_ms(jitter)
+    )
+}
+
+fn format_ms(d: Duration) -> String {
+    format!("{:.3} ms", as_ms(d))
+}
+
+fn as_ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.
+}
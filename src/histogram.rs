@@ -0,0 +1,146 @@
+// The conrod derive macros generate their impls inside anonymous constants
+#![allow(non_local_definitions)]
+use crate::{
+    stats::{Histogram, HISTOGRAM_BINS},
+    widget::YAxis,
+};
+use conrod_core::Borderable;
+use std::time::Duration;
+
+use conrod_core::{
+    builder_method, color::Color, widget, widget_ids, Colorable, Positionable, Rect, Widget,
+    WidgetCommon, WidgetStyle,
+};
+
+/// Distribution of the latencies shown on a graph, drawn beside it on the same vertical scale
+#[derive(Debug, WidgetCommon)]
+pub struct HistogramWidget<'a> {
+    #[conrod(common_builder)]
+    common: widget::CommonBuilder,
+    latencies: &'a [Duration],
+    y_axis: YAxis,
+    style: Style,
+}
+
+widget_ids!(
+    struct Ids {
+        border,
+        bins[],
+    }
+);
+
+pub struct State {
+    ids: Ids,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, WidgetStyle)]
+pub struct Style {
+    #[conrod(default = "theme.border_color")]
+    pub color: Option<Color>,
+
+    #[conrod(default = "1.0")]
+    pub border: Option<f64>,
+    #[conrod(default = "theme.border_color")]
+    pub border_color: Option<Color>,
+}
+
+// Space left on the right of the bars, in pixels
+const RIGHT_PADDING: f64 = 8.;
+
+impl<'a> HistogramWidget<'a> {
+    pub fn new(latencies: &'a [Duration], y_axis: YAxis) -> Self {
+        Self {
+            common: widget::CommonBuilder::default(),
+            latencies,
+            y_axis,
+            style: Style::default(),
+        }
+    }
+}
+
+impl Widget for HistogramWidget<'_> {
+    type State = State;
+    type Style = Style;
+    type Event = ();
+
+    fn init_state(&self, id_gen: widget::id::Generator<'_>) -> <Self as Widget>::State {
+        State {
+            ids: Ids::new(id_gen),
+        }
+    }
+    fn style(&self) -> <Self as Widget>::Style {
+        self.style
+    }
+
+    fn update(self, args: widget::UpdateArgs<'_, '_, '_, '_, Self>) {
+        let widget::UpdateArgs {
+            id,
+            rect: widget_area,
+            state,
+            ui,
+            ..
+        } = args;
+
+        // Same height as the graph's area, so that the bins line up with its latencies
+        let area = Rect::from_corners(
+            [widget_area.left(), self.y_axis.bottom],
+            [widget_area.right() - RIGHT_PADDING, self.y_axis.top],
+        );
+        let border_color = self.style.border_color(ui.theme());
+
+        let mut histogram = Histogram::default();
+        for &lat in self.latencies {
+            histogram.add(lat);
+        }
+        let counts = histogram.counts();
+        let max_count = counts.iter().copied().max().unwrap_or(0).max(1);
+
+        if state.ids.bins.len() < HISTOGRAM_BINS {
+            state.update(|state| {
+                state
+                    .ids
+                    .bins
+                    .resize(HISTOGRAM_BINS, &mut ui.widget_id_generator())
+            });
+        }
+        let bin_color = self.style.color(ui.theme()).alpha(0.5);
+        for (bin, &count) in counts.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            let (low, high) = Histogram::bin_range(bin);
+            let w = area.w() * count as f64 / max_count as f64;
+            let bar = Rect::from_corners(
+                [area.left(), self.y_axis.lat_to_y(low)],
+                [area.left() + w, self.y_axis.lat_to_y(high)],
+            );
+            if let Some(rct) = bar.overlap(area) {
+                widget::Rectangle::fill(rct.dim())
+                    .xy(rct.xy())
+                    .color(bin_color)
+                    .parent(id)
+                    .graphics_for(id)
+                    .set(state.ids.bins[bin], ui);
+            }
+        }
+
+        widget::Rectangle::outline_styled(
+            area.dim(),
+            widget::line::Style::solid().thickness(self.style.border(ui.theme())),
+        )
+        .xy(area.xy())
+        .color(border_color)
+        .parent(id)
+        .graphics_for(id)
+        .set(state.ids.border, ui);
+    }
+}
+
+impl Colorable for HistogramWidget<'_> {
+    builder_method!(color { style.color = Some(Color) });
+}
+
+impl Borderable for HistogramWidget<'_> {
+    builder_method!(border { style.border = Some(f64) });
+    builder_method!(border_color { style.border_color = Some(Color) });
+}
@@ -3,14 +3,24 @@
     windows_subsystem = "windows"
 )]
 use std::fs::read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use clap::{crate_version, App, Arg, ArgMatches};
 use log::*;
 
+mod aggregate;
+mod alert;
 mod app;
+mod export;
+mod headless;
+mod histogram;
+mod metrics;
+mod probe;
+mod record;
 mod ringbuf;
+mod settings_panel;
+mod stats;
 mod widget;
 
 fn main() {
@@ -36,13 +46,43 @@ fn run() {
         .arg(Arg::with_name("remote")
             .short("r")
             .long("remote")
-            .help("Remote host for the UDP Echo server. Port will be assumed to be 7 if not included (e.g example.org == example.org:7)")
-            .takes_value(true))
+            .help("Remote host for the UDP Echo server. Port will be assumed to be 7 if not included (e.g example.org == example.org:7). May be given multiple times to monitor several hosts, each optionally prefixed by a label (e.g office=192.168.1.1). Prefix the host with icmp:// to send ICMP echo requests instead (e.g gateway=icmp://192.168.1.1), or with tcp:// to time TCP connections to the given port (e.g web=tcp://example.org:443)")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1))
         .arg(Arg::with_name("rate")
             .short("t")
             .long("rate")
             .help("Polling rate, as the delay in milliseconds between polls")
             .default_value("100"))
+        .arg(Arg::with_name("payload-size")
+            .short("s")
+            .long("payload-size")
+            .help("Size in bytes of the echo packets, at least 25 bytes for the header and at most 65507")
+            .takes_value(true))
+        .arg(Arg::with_name("history")
+            .short("H")
+            .long("history")
+            .help("How much history to keep for each host, as a number of pings (e.g 5000) or a duration (e.g 90s, 10m, 2h). Defaults to 1000 pings")
+            .takes_value(true))
+        .arg(Arg::with_name("percentiles")
+            .long("percentiles")
+            .help("Comma-separated percentiles of the visible latencies to mark on the vertical axis, or an empty string for none. Defaults to 50,95,99")
+            .takes_value(true))
+        .arg(Arg::with_name("thresholds")
+            .long("thresholds")
+            .help("Comma-separated latency thresholds in milliseconds marked on the graphs, each with the color of the latencies under it, optionally followed by the color of those above (e.g 30:green,80:yellow,red). Colors are green, yellow, orange, red, blue, purple, grey or #rrggbb. An empty string removes them")
+            .takes_value(true))
+        .arg(Arg::with_name("alert")
+            .long("alert")
+            .help("Raise an alert when the average latency of the responses to the last pings goes above a number of milliseconds (latency:<ms>[:<pings>], e.g latency:80:20), when the loss over the last pings goes above a percentage (loss:<percent>[:<pings>], e.g loss:5:100) or when no response came for a number of seconds (no-response:<secs>). The number of pings defaults to 10. May be given multiple times")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("alert-command")
+            .long("alert-command")
+            .help("Shell command run when an alert is raised or cleared, with the target's name in LATGRAPH_TARGET and the reason for the alert in LATGRAPH_ALERT (empty when cleared)")
+            .takes_value(true))
         .arg(Arg::with_name("paused")
             .short("p")
             .long("paused")
@@ -51,7 +91,42 @@ fn run() {
             .short("P")
             .long("running")
             .conflicts_with("paused")
-            .help("Don't immediately start polling the server"));
+            .help("Don't immediately start polling the server"))
+        .arg(Arg::with_name("export")
+            .short("e")
+            .long("export")
+            .help("File to export the history of pings to, as JSON if its extension is .json or CSV otherwise. Written when pressing E in the window, or before exiting in headless mode. Without it, E exports to a new CSV file in the working directory (JSON with Shift+E)")
+            .takes_value(true))
+        .arg(Arg::with_name("record")
+            .long("record")
+            .help("Write every ping and response to the given file, to be replayed later with --replay")
+            .takes_value(true))
+        .arg(Arg::with_name("replay")
+            .long("replay")
+            .conflicts_with_all(&["record", "headless", "remote"])
+            .help("Replay a session recorded with --record instead of sending pings. Space pauses, left and right arrows seek 10 seconds back and forth")
+            .takes_value(true))
+        .arg(Arg::with_name("headless")
+            .long("headless")
+            .help("Don't open a window, print the results to the standard output instead"))
+        .arg(Arg::with_name("count")
+            .short("n")
+            .long("count")
+            .requires("headless")
+            .help("Exit after sending this many pings to each host, printing a summary")
+            .takes_value(true))
+        .arg(Arg::with_name("duration")
+            .short("d")
+            .long("duration")
+            .requires("headless")
+            .help("Exit after this many seconds, printing a summary")
+            .takes_value(true))
+        .arg(Arg::with_name("summary-interval")
+            .short("i")
+            .long("summary-interval")
+            .requires("headless")
+            .help("Print a summary every this many seconds instead of a line for every response")
+            .takes_value(true));
     if cfg!(feature = "config") {
         app = app
             .arg(Arg::with_name("config")
@@ -64,30 +139,163 @@ fn run() {
                 .long("no-config-save")
                 .help("Disable the saving of settings to the config file, the file will only be read on startup."));
     }
+    if cfg!(feature = "metrics") {
+        app = app.arg(Arg::with_name("metrics")
+            .long("metrics")
+            .conflicts_with("replay")
+            .help("Serve the ping counters and a latency histogram of each host in the Prometheus format at http://<address>/metrics, given as a port on the loopback interface (e.g 9898) or an address and port (e.g 0.0.0.0:9898)")
+            .takes_value(true));
+    }
     let matches = app.get_matches();
 
-    let (config_location, mut settings) = parse_config(&matches);
-    if let Some(remote) = matches.value_of("remote") {
-        settings.remote_host = String::from(remote);
+    let (mut config_location, mut settings) = parse_config(&matches);
+    let replay = matches.value_of("replay").map(|path| {
+        record::Recording::load(Path::new(path))
+            .unwrap_or_else(|e| panic!("Couldn't load recording {}: {}", path, e))
+    });
+    if let Some(remotes) = matches.values_of("remote") {
+        settings.targets = remotes
+            .map(|r| {
+                app::TargetSettings::parse(r)
+                    .unwrap_or_else(|e| panic!("Invalid remote argument '{}': {}", r, e))
+            })
+            .collect();
     }
+    settings.targets.retain(|t| !t.host.is_empty());
     if let Some(rate) = matches.value_of("rate") {
-        settings.delay =
-            Duration::from_millis(rate.parse().expect("Invalid number for rate argument"));
+        settings.delay = match rate.parse().map(Duration::from_millis) {
+            Ok(delay) if delay > Duration::ZERO && delay <= app::MAX_DELAY => delay,
+            _ => panic!(
+                "Invalid rate '{}', expected milliseconds up to {}",
+                rate,
+                app::MAX_DELAY.as_millis()
+            ),
+        };
+    }
+    if let Some(size) = matches.value_of("payload-size") {
+        let size: usize = size
+            .parse()
+            .expect("Invalid number for payload-size argument");
+        if !(probe::packet::HEADER_LEN..=probe::packet::MAX_PAYLOAD_SIZE).contains(&size) {
+            panic!(
+                "Payload size must be between {} and {} bytes",
+                probe::packet::HEADER_LEN,
+                probe::packet::MAX_PAYLOAD_SIZE
+            );
+        }
+        settings.payload_size = size;
+    }
+    if let Some(history) = matches.value_of("history") {
+        settings.history = app::HistoryLength::parse(history)
+            .unwrap_or_else(|e| panic!("Invalid history argument '{}': {}", history, e));
+    }
+    if let Some(percentiles) = matches.value_of("percentiles") {
+        settings.percentiles = percentiles
+            .split(',')
+            .filter(|p| !p.is_empty())
+            .map(|p| match p.trim().parse::<f64>() {
+                Ok(p) if (0.0..=100.).contains(&p) => p,
+                _ => panic!(
+                    "Invalid percentile '{}', expected a number between 0 and 100",
+                    p
+                ),
+            })
+            .collect();
+    }
+    if let Some(thresholds) = matches.value_of("thresholds") {
+        settings.thresholds = app::Threshold::parse_list(thresholds)
+            .unwrap_or_else(|e| panic!("Invalid thresholds '{}': {}", thresholds, e));
+    }
+    if let Some(rules) = matches.values_of("alert") {
+        settings.alerts = rules
+            .map(|r| {
+                alert::AlertRule::parse(r)
+                    .unwrap_or_else(|e| panic!("Invalid alert rule '{}': {}", r, e))
+            })
+            .collect();
+    }
+    if let Some(command) = matches.value_of("alert-command") {
+        settings.alert_command = String::from(command);
     }
     if matches.is_present("paused") || matches.is_present("running") {
         settings.running = matches.is_present("running");
     }
-    settings.running &= !settings.remote_host.is_empty();
+    settings.running &= settings.has_hosts();
+    if let Some(recording) = &replay {
+        // The recording's targets only last for this session, don't save them
+        config_location = None;
+        settings.targets = recording.targets.clone();
+        settings.running = true;
+    }
 
+    if matches.is_present("no-config-save") {
+        config_location = None;
+    }
     if let Some(path) = &config_location {
         if let Err(err) = settings.save(path) {
             error!("Couldn't save settings: {}", err);
         }
     }
 
+    let recorder = matches.value_of("record").map(|path| {
+        record::Recorder::create(Path::new(path), &settings)
+            .unwrap_or_else(|e| panic!("Couldn't create recording {}: {}", path, e))
+    });
+
+    let export_path = matches.value_of("export").map(PathBuf::from);
+
+    #[cfg(feature = "metrics")]
+    let metrics = matches.value_of("metrics").map(|address| {
+        let metrics = metrics::Metrics::new(&settings.targets);
+        metrics::serve(address, metrics.clone())
+            .unwrap_or_else(|e| panic!("Couldn't serve metrics on {}: {}", address, e));
+        metrics
+    });
+    #[cfg(not(feature = "metrics"))]
+    let metrics = None;
+
+    if matches.is_present("headless") {
+        if !settings.has_hosts() {
+            eprintln!("No remote host to send pings to, see --help");
+            std::process::exit(2);
+        }
+        let parse_secs = |name: &str| {
+            matches.value_of(name).map(|secs| {
+                Duration::from_secs_f64(
+                    secs.parse()
+                        .unwrap_or_else(|_| panic!("Invalid number for {} argument", name)),
+                )
+            })
+        };
+        let options = headless::HeadlessOptions {
+            recorder,
+            metrics,
+            export_path: export_path.clone(),
+            count: matches.value_of("count").map(|n| match n.parse() {
+                Ok(n) if n > 0 => n,
+                _ => panic!("Invalid count '{}', expected at least one ping", n),
+            }),
+            duration: parse_secs("duration"),
+            summary_interval: parse_secs("summary-interval"),
+        };
+        info!(
+            "Starting headless with settings {:?}, {:?}",
+            settings, options
+        );
+        let success = headless::run(settings, options);
+        std::process::exit(if success { 0 } else { 1 });
+    }
+
     info!("Starting app with settings {:?}", settings);
 
-    app::LatGraphApp::start(settings, config_location);
+    app::LatGraphApp::start(
+        settings,
+        config_location,
+        export_path,
+        recorder,
+        metrics,
+        replay,
+    );
 }
 
 #[cfg(not(feature = "config"))]
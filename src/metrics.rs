@@ -0,0 +1,367 @@
+// Without the feature there's no server, so the counters are never created and only compiled
+#![cfg_attr(not(feature = "metrics"), allow(dead_code))]
+use crate::{app::TargetSettings, stats::LOSS_TIMEOUT};
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Connections to the metrics server are closed if a request takes longer than this to arrive
+#[cfg(feature = "metrics")]
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Upper bounds of the latency histogram's buckets, in seconds
+const LATENCY_BUCKETS: [f64; 14] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1., 2.5, 5., 10.,
+];
+
+/// Counters of the pings sent to each target, shared with the HTTP server that exposes them
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    targets: Arc<Mutex<Vec<TargetMetrics>>>,
+}
+
+/// Reads one of the counters of a target
+type Counter = fn(&TargetMetrics) -> u64;
+
+#[derive(Debug)]
+struct TargetMetrics {
+    label: String,
+    host: String,
+    sent: u64,
+    received: u64,
+    lost: u64,
+    /// Number of latencies in each of `LATENCY_BUCKETS`, not cumulated
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    latency_sum: Duration,
+    /// Sequence number and time of the pings awaiting a response, oldest first
+    pending: VecDeque<(u64, Instant)>,
+}
+
+impl Metrics {
+    pub fn new(targets: &[TargetSettings]) -> Self {
+        Metrics {
+            targets: Arc::new(Mutex::new(targets.iter().map(TargetMetrics::new).collect())),
+        }
+    }
+
+    /// Adds counters for a new target, after the others
+    pub fn push(&self, target: &TargetSettings) {
+        self.targets
+            .lock()
+            .unwrap()
+            .push(TargetMetrics::new(target));
+    }
+
+    /// Removes the counters of a target, the following ones take its index
+    pub fn remove(&self, index: usize) {
+        let mut targets = self.targets.lock().unwrap();
+        if index < targets.len() {
+            targets.remove(index);
+        }
+    }
+
+    /// Starts counting again from zero for a target, e.g after its probe is changed
+    pub fn restart(&self, index: usize, target: &TargetSettings) {
+        if let Some(metrics) = self.targets.lock().unwrap().get_mut(index) {
+            *metrics = TargetMetrics::new(target);
+        }
+    }
+
+    /// Updates the labels and hosts of the targets, keeping their counters
+    pub fn relabel(&self, targets: &[TargetSettings]) {
+        for (metrics, target) in self.targets.lock().unwrap().iter_mut().zip(targets) {
+            metrics.label = String::from(target.display_label());
+            metrics.host = target.host.clone();
+        }
+    }
+
+    pub fn sent(&self, target: usize, seq: u64, time: Instant) {
+        if let Some(target) = self.targets.lock().unwrap().get_mut(target) {
+            target.sent += 1;
+            target.pending.push_back((seq, time));
+            target.expire(time);
+        }
+    }
+
+    /// Counts the response to the given ping, unless it was already counted as lost
+    pub fn received(&self, target: usize, seq: u64, time: Instant) {
+        if let Some(target) = self.targets.lock().unwrap().get_mut(target) {
+            if let Some(pos) = target.pending.iter().position(|&(s, _)| s == seq) {
+                let (_, sent_time) = target.pending.remove(pos).unwrap();
+                let lat = time.saturating_duration_since(sent_time);
+                target.received += 1;
+                target.latency_sum += lat;
+                if let Some(bucket) = LATENCY_BUCKETS
+                    .iter()
+                    .position(|&le| lat.as_secs_f64() <= le)
+                {
+                    target.buckets[bucket] += 1;
+                }
+            }
+        }
+    }
+
+    /// Writes the counters in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut targets = self.targets.lock().unwrap();
+        let now = Instant::now();
+        for target in targets.iter_mut() {
+            target.expire(now);
+        }
+
+        let mut out = String::new();
+        let counters: [(&str, &str, Counter); 3] = [
+            ("sent", "Pings sent to the target", |t| t.sent),
+            ("received", "Responses received in time", |t| t.received),
+            ("lost", "Pings left without a response", |t| t.lost),
+        ];
+        for &(name, help, value) in &counters {
+            let _ = writeln!(out, "# HELP latgraph_pings_{}_total {}", name, help);
+            let _ = writeln!(out, "# TYPE latgraph_pings_{}_total counter", name);
+            for target in targets.iter() {
+                let _ = writeln!(
+                    out,
+                    "latgraph_pings_{}_total{{{}}} {}",
+                    name,
+                    target.labels(),
+                    value(target)
+                );
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP latgraph_latency_seconds Round-trip time of the responses"
+        );
+        let _ = writeln!(out, "# TYPE latgraph_latency_seconds histogram");
+        for target in targets.iter() {
+            let labels = target.labels();
+            let mut count = 0;
+            for (le, n) in LATENCY_BUCKETS.iter().zip(&target.buckets) {
+                count += n;
+                let _ = writeln!(
+                    out,
+                    "latgraph_latency_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, le, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "latgraph_latency_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, target.received
+            );
+            let _ = writeln!(
+                out,
+                "latgraph_latency_seconds_sum{{{}}} {}",
+                labels,
+                target.latency_sum.as_secs_f64()
+            );
+            let _ = writeln!(
+                out,
+                "latgraph_latency_seconds_count{{{}}} {}",
+                labels, target.received
+            );
+        }
+        out
+    }
+}
+
+impl TargetMetrics {
+    fn new(target: &TargetSettings) -> Self {
+        TargetMetrics {
+            label: String::from(target.display_label()),
+            host: target.host.clone(),
+            sent: 0,
+            received: 0,
+            lost: 0,
+            buckets: [0; LATENCY_BUCKETS.len()],
+            latency_sum: Duration::ZERO,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Counts the pings that went unanswered for too long as lost
+    fn expire(&mut self, now: Instant) {
+        while let Some(&(_, time)) = self.pending.front() {
+            if now.saturating_duration_since(time) < LOSS_TIMEOUT {
+                break;
+            }
+            self.pending.pop_front();
+            self.lost += 1;
+        }
+    }
+
+    fn labels(&self) -> String {
+        format!(
+            "target=\"{}\",host=\"{}\"",
+            escape_label(&self.label),
+            escape_label(&self.host)
+        )
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Serves the metrics at `/metrics` over HTTP on the given address (or port on the loopback
+/// interface), handling each connection on its own thread
+#[cfg(feature = "metrics")]
+pub fn serve(address: &str, metrics: Metrics) -> std::io::Result<()> {
+    use log::*;
+    use std::{net::TcpListener, thread};
+
+    let listener = match address.parse::<u16>() {
+        Ok(port) => TcpListener::bind(("127.0.0.1", port))?,
+        Err(_) => TcpListener::bind(address)?,
+    };
+    info!(
+        "Serving metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let metrics = metrics.clone();
+                    // A slow client can't hold up the others
+                    thread::spawn(move || respond(stream, &metrics));
+                }
+                Err(e) => warn!("Couldn't accept metrics connection: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Answers a single request for the metrics, then closes the connection
+#[cfg(feature = "metrics")]
+fn respond(mut stream: std::net::TcpStream, metrics: &Metrics) {
+    use log::*;
+    use std::io::{BufRead, BufReader};
+
+    // Scrapers send small requests, don't keep idle connections around
+    let _ = stream.set_read_timeout(Some(REQUEST_TIMEOUT));
+    let _ = stream.set_write_timeout(Some(REQUEST_TIMEOUT));
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(&stream);
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    // Skip the headers
+    let mut header = String::new();
+    while reader.read_line(&mut header).is_ok_and(|n| n > 2) {
+        header.clear();
+    }
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) if path.split('?').next() == Some("/metrics") => {
+            let body = metrics.render();
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        _ => {
+            String::from("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+        }
+    };
+    if let Err(e) = std::io::Write::write_all(&mut stream, response.as_bytes()) {
+        debug!("Couldn't send metrics: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(arg: &str) -> TargetSettings {
+        TargetSettings::parse(arg).unwrap()
+    }
+
+    #[test]
+    fn escape_labels() {
+        assert_eq!(escape_label("example.com:7"), "example.com:7");
+        assert_eq!(escape_label("a \"b\" \\c\nd"), "a \\\"b\\\" \\\\c\\nd");
+    }
+
+    #[test]
+    fn render() {
+        let metrics = Metrics::new(&[target("web=tcp://host:80"), target("we\"ird")]);
+        let now = Instant::now();
+        for seq in 0..3 {
+            metrics.sent(0, seq, now);
+        }
+        metrics.received(0, 0, now + Duration::from_millis(3));
+        metrics.received(0, 1, now + Duration::from_millis(30));
+        // Already counted, or never sent
+        metrics.received(0, 1, now + Duration::from_millis(40));
+        metrics.received(0, 7, now + Duration::from_millis(40));
+        // Left without a response for longer than the loss timeout
+        metrics.sent(1, 0, now - LOSS_TIMEOUT);
+
+        let out = metrics.render();
+        let lines: Vec<&str> = out.lines().collect();
+        for expected in [
+            "# TYPE latgraph_pings_sent_total counter",
+            "latgraph_pings_sent_total{target=\"web\",host=\"host:80\"} 3",
+            "latgraph_pings_received_total{target=\"web\",host=\"host:80\"} 2",
+            "latgraph_pings_lost_total{target=\"web\",host=\"host:80\"} 0",
+            "latgraph_pings_sent_total{target=\"we\\\"ird\",host=\"we\\\"ird\"} 1",
+            "latgraph_pings_lost_total{target=\"we\\\"ird\",host=\"we\\\"ird\"} 1",
+            "# TYPE latgraph_latency_seconds histogram",
+            // Buckets are cumulative
+            "latgraph_latency_seconds_bucket{target=\"web\",host=\"host:80\",le=\"0.0025\"} 0",
+            "latgraph_latency_seconds_bucket{target=\"web\",host=\"host:80\",le=\"0.005\"} 1",
+            "latgraph_latency_seconds_bucket{target=\"web\",host=\"host:80\",le=\"0.025\"} 1",
+            "latgraph_latency_seconds_bucket{target=\"web\",host=\"host:80\",le=\"0.05\"} 2",
+            "latgraph_latency_seconds_bucket{target=\"web\",host=\"host:80\",le=\"10\"} 2",
+            "latgraph_latency_seconds_bucket{target=\"web\",host=\"host:80\",le=\"+Inf\"} 2",
+            "latgraph_latency_seconds_sum{target=\"web\",host=\"host:80\"} 0.033",
+            "latgraph_latency_seconds_count{target=\"web\",host=\"host:80\"} 2",
+            "latgraph_latency_seconds_count{target=\"we\\\"ird\",host=\"we\\\"ird\"} 0",
+        ] {
+            assert!(
+                lines.contains(&expected),
+                "missing {:?} in\n{}",
+                expected,
+                out
+            );
+        }
+    }
+
+    #[test]
+    fn relabel_keeps_counters() {
+        let metrics = Metrics::new(&[target("a")]);
+        metrics.sent(0, 0, Instant::now());
+        metrics.relabel(&[target("b=c")]);
+        assert!(metrics
+            .render()
+            .contains("latgraph_pings_sent_total{target=\"b\",host=\"c\"} 1"));
+        metrics.restart(0, &target("b=c"));
+        assert!(metrics
+            .render()
+            .contains("latgraph_pings_sent_total{target=\"b\",host=\"c\"} 0"));
+    }
+
+    #[test]
+    fn add_and_remove_targets() {
+        let metrics = Metrics::new(&[target("a"), target("b")]);
+        metrics.sent(1, 0, Instant::now());
+        metrics.push(&target("c"));
+        metrics.remove(0);
+        // The counters follow their target to its new index
+        metrics.sent(1, 0, Instant::now());
+        let out = metrics.render();
+        assert!(out.contains("latgraph_pings_sent_total{target=\"b\",host=\"b\"} 1"));
+        assert!(out.contains("latgraph_pings_sent_total{target=\"c\",host=\"c\"} 1"));
+        assert!(!out.contains("target=\"a\""));
+    }
+}
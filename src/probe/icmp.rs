@@ -0,0 +1,211 @@
+use super::{
+    packet::{self, Packet},
+    Prober, Response, RECV_TIMEOUT,
+};
+use crate::app::LatGraphSettings;
+use std::{
+    convert::TryInto,
+    io,
+    net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use log::*;
+use socket2::{Domain, Protocol, Socket, Type};
+
+pub const HEADER_LEN: usize = 8;
+/// Largest payload that fits in an ICMP echo request, after its header
+pub const MAX_PAYLOAD_SIZE: usize = packet::MAX_PAYLOAD_SIZE - HEADER_LEN;
+/// Maximum length of an IPv4 header, which raw sockets include in received packets
+pub const MAX_IP_HEADER_LEN: usize = 60;
+
+const ECHO_REPLY: u8 = 0;
+const ECHO_REQUEST: u8 = 8;
+
+/// Sends ICMP echo requests, carrying the same payload as the UDP Echo packets
+pub struct IcmpProber {
+    /// ICMP socket, wrapped as an `UdpSocket` since both are datagram sockets using the same
+    /// send/recv calls
+    socket: UdpSocket,
+    /// Raw sockets receive the IP header along with the ICMP packet, and all ICMP traffic
+    /// instead of only the replies to our own requests
+    raw: bool,
+    nonce: u32,
+    payload_size: AtomicUsize,
+    /// Only used by the receiver thread, large enough for the responses to pings of any size
+    recv_buf: Mutex<Vec<u8>>,
+}
+
+impl IcmpProber {
+    /// Opens an unprivileged ICMP datagram socket if the system allows it (on Linux, see the
+    /// `net.ipv4.ping_group_range` sysctl), otherwise falls back to a raw socket
+    pub fn new() -> io::Result<IcmpProber> {
+        let (socket, raw) = match Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::ICMPV4)) {
+            Ok(socket) => (socket, false),
+            Err(e) => {
+                debug!(
+                    "Couldn't open ICMP datagram socket ({}), trying a raw socket",
+                    e
+                );
+                let socket = Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4))?;
+                (socket, true)
+            }
+        };
+        socket.set_read_timeout(Some(RECV_TIMEOUT))?;
+        Ok(IcmpProber {
+            socket: socket.into(),
+            raw,
+            nonce: packet::session_nonce(),
+            payload_size: AtomicUsize::new(packet::HEADER_LEN),
+            recv_buf: Mutex::new(vec![0u8; MAX_IP_HEADER_LEN + HEADER_LEN + MAX_PAYLOAD_SIZE]),
+        })
+    }
+
+    fn ident(&self) -> u16 {
+        self.nonce as u16
+    }
+}
+
+impl Prober for IcmpProber {
+    fn connect(&self, host: &str) -> io::Result<()> {
+        self.socket.connect(resolve(host)?)
+    }
+
+    fn reconfigure(&self, settings: &LatGraphSettings) {
+        let size = settings
+            .payload_size
+            .clamp(packet::HEADER_LEN, MAX_PAYLOAD_SIZE);
+        self.payload_size.store(size, Ordering::Relaxed);
+    }
+
+    fn send(&self, seq: u64) -> io::Result<()> {
+        let size = self.payload_size.load(Ordering::Relaxed);
+        let payload = Packet::new(self.nonce, seq, size).to_bytes();
+        self.socket
+            .send(&echo_request(self.ident(), seq as u16, &payload))
+            .map(|_| ())
+    }
+
+    fn recv(&self) -> io::Result<Response> {
+        let mut buf = self.recv_buf.lock().unwrap();
+        loop {
+            let len = self.socket.recv(&mut buf)?;
+            let now = Instant::now();
+            let data = match echo_reply_payload(&buf[..len], self.raw, self.ident()) {
+                Some(data) => data,
+                None => continue, // Not a reply to one of our requests
+            };
+            // The payload size may have changed since the ping was sent, so the response is
+            // checked against its own length rather than the current size
+            return match Packet::parse(data, self.nonce, data.len()) {
+                Ok(packet) => Ok(Response::Pong(packet.seq, now)),
+                Err(e) => {
+                    warn!("Dropping invalid ICMP echo reply: {}", e);
+                    Ok(Response::Invalid)
+                }
+            };
+        }
+    }
+}
+
+/// Resolves the host to an IPv4 address. A port may be given but will be ignored
+pub fn resolve(host: &str) -> io::Result<SocketAddr> {
+    let addrs = match host.parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, 0)],
+        Err(_) if host.contains(':') => host.to_socket_addrs()?.collect(),
+        Err(_) => (host, 0).to_socket_addrs()?.collect(),
+    };
+    addrs
+        .into_iter()
+        .find(SocketAddr::is_ipv4)
+        .map(|addr| SocketAddr::new(addr.ip(), 0))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no IPv4 address for host"))
+}
+
+/// Builds an echo request carrying the given payload
+pub fn echo_request(ident: u16, seq: u16, payload: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN + payload.len());
+    buf.extend_from_slice(&[ECHO_REQUEST, 0, 0, 0]);
+    buf.extend_from_slice(&ident.to_be_bytes());
+    buf.extend_from_slice(&seq.to_be_bytes());
+    buf.extend_from_slice(payload);
+    let checksum = checksum(&buf);
+    buf[2..4].copy_from_slice(&checksum.to_be_bytes());
+    buf
+}
+
+/// Extracts the payload of a received echo reply. Returns `None` for other ICMP messages, and
+/// on raw sockets for replies to other processes' requests
+pub fn echo_reply_payload(buf: &[u8], raw: bool, ident: u16) -> Option<&[u8]> {
+    let buf = if raw {
+        let ip_header_len = (*buf.first()? & 0x0f) as usize * 4;
+        buf.get(ip_header_len..)?
+    } else {
+        buf
+    };
+    if buf.len() < HEADER_LEN || buf[0] != ECHO_REPLY {
+        return None;
+    }
+    // Datagram sockets have their identifier rewritten by the kernel, which also filters replies
+    if raw && u16::from_be_bytes(buf[4..6].try_into().unwrap()) != ident {
+        return None;
+    }
+    Some(&buf[HEADER_LEN..])
+}
+
+/// Internet checksum (RFC 1071)
+fn checksum(buf: &[u8]) -> u16 {
+    let mut sum = buf
+        .chunks(2)
+        .map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]) as u32)
+        .sum::<u32>();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum_vectors() {
+        // Example from RFC 1071, section 3
+        assert_eq!(
+            checksum(&[0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7]),
+            !0xddf2
+        );
+        // Echo request with identifier 1, sequence number 1 and no payload
+        assert_eq!(checksum(&[8, 0, 0, 0, 0, 1, 0, 1]), 0xf7fd);
+        // Odd length, the last byte is padded with a zero
+        assert_eq!(checksum(&[0x12, 0x34, 0x56]), !(0x1234 + 0x5600));
+    }
+
+    #[test]
+    fn echo_request_checksum() {
+        let request = echo_request(1, 1, &[]);
+        assert_eq!(request, [8, 0, 0xf7, 0xfd, 0, 1, 0, 1]);
+        // Summing a packet along with its checksum gives zero
+        let request = echo_request(0x1234, 42, b"latgraph");
+        assert_eq!(checksum(&request), 0);
+    }
+
+    #[test]
+    fn reply_payload() {
+        let mut reply = echo_request(7, 1, b"data");
+        reply[0] = ECHO_REPLY;
+        assert_eq!(echo_reply_payload(&reply, false, 0), Some(&b"data"[..]));
+
+        let mut raw = vec![0x45];
+        raw.extend_from_slice(&[0; 19]);
+        raw.extend_from_slice(&reply);
+        assert_eq!(echo_reply_payload(&raw, true, 7), Some(&b"data"[..]));
+        assert_eq!(echo_reply_payload(&raw, true, 8), None);
+        assert_eq!(echo_reply_payload(&echo_request(7, 1, &[]), false, 7), None);
+    }
+}
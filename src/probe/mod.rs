@@ -0,0 +1,332 @@
+use crate::app::{AppError, AppEvent, LatGraphSettings, TargetId};
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use glium::glutin::event_loop::EventLoopProxy;
+use log::*;
+use thread_priority::ThreadPriority;
+
+mod icmp;
+pub mod packet;
+mod tcp;
+mod udp;
+
+/// A way of measuring the latency to a host. Probers are shared between a sender and a receiver
+/// thread, so their methods take `&self` and need to handle their own synchronization.
+pub trait Prober: Send + Sync {
+    /// Connects to the given host, or resolves it for probes that don't keep a connection. Also
+    /// called with the same host to reconnect after a failed send.
+    fn connect(&self, host: &str) -> io::Result<()>;
+
+    /// Applies new settings, called before connecting and whenever they change
+    fn reconfigure(&self, _settings: &LatGraphSettings) {}
+
+    /// Sends the ping with the given sequence number
+    fn send(&self, seq: u64) -> io::Result<()>;
+
+    /// Waits for the next response. Implementations should give up after `RECV_TIMEOUT` with an
+    /// error of kind `WouldBlock` or `TimedOut`, so that the receiver thread can stop in time.
+    fn recv(&self) -> io::Result<Response>;
+}
+
+#[derive(Debug)]
+pub enum Response {
+    /// Response to the ping with the given sequence number, received at the given time
+    Pong(u64, Instant),
+    /// Response that couldn't be matched to a ping we sent
+    Invalid,
+}
+
+pub const RECV_TIMEOUT: Duration = Duration::from_millis(500);
+/// Delay before trying again to resolve a host, doubled after each failure up to the maximum
+const RESOLVE_RETRY_MIN: Duration = Duration::from_secs(1);
+const RESOLVE_RETRY_MAX: Duration = Duration::from_secs(60);
+
+/// Destination of the events produced by the network threads
+pub trait EventSink: Clone + Send + 'static {
+    /// Fails if the receiving end has shut down
+    fn send(&self, event: AppEvent) -> Result<(), ()>;
+}
+
+impl EventSink for EventLoopProxy<AppEvent> {
+    fn send(&self, event: AppEvent) -> Result<(), ()> {
+        self.send_event(event).map_err(|_| ())
+    }
+}
+
+impl EventSink for mpsc::Sender<AppEvent> {
+    fn send(&self, event: AppEvent) -> Result<(), ()> {
+        mpsc::Sender::send(self, event).map_err(|_| ())
+    }
+}
+
+/// Method used to measure the latency to a target
+#[cfg_attr(
+    feature = "config",
+    derive(serde_derive::Serialize, serde_derive::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq)]
+pub enum ProbeKind {
+    /// UDP Echo protocol (RFC 862), port 7 by default
+    #[default]
+    UdpEcho,
+    /// ICMP echo requests, as sent by the `ping` command
+    Icmp,
+    /// Time to establish a TCP connection (or have it refused), port 80 by default
+    Tcp,
+}
+
+impl ProbeKind {
+    pub fn open(self) -> io::Result<Arc<dyn Prober>> {
+        Ok(match self {
+            ProbeKind::UdpEcho => Arc::new(udp::UdpProber::new()?),
+            ProbeKind::Icmp => Arc::new(icmp::IcmpProber::new()?),
+            ProbeKind::Tcp => Arc::new(tcp::TcpProber::new()),
+        })
+    }
+
+    /// Scheme prefixing hosts on the command line to select this probe
+    pub fn scheme(self) -> &'static str {
+        match self {
+            ProbeKind::UdpEcho => "udp",
+            ProbeKind::Icmp => "icmp",
+            ProbeKind::Tcp => "tcp",
+        }
+    }
+
+    pub fn from_scheme(scheme: &str) -> Option<ProbeKind> {
+        match scheme {
+            "udp" => Some(ProbeKind::UdpEcho),
+            "icmp" => Some(ProbeKind::Icmp),
+            "tcp" => Some(ProbeKind::Tcp),
+            _ => None,
+        }
+    }
+}
+
+/// Starts the sender and receiver threads probing the given target, the only one in the settings
+/// sent to them (see `LatGraphSettings::for_target`). They stop when the settings channel is closed.
+pub fn start<S: EventSink>(
+    target: TargetId,
+    kind: ProbeKind,
+    settings_rx: mpsc::Receiver<LatGraphSettings>,
+    event_tx_rcv: S,
+) {
+    debug!(
+        "Initializing {:?} network threads for target {}",
+        kind, target
+    );
+    let prober_snd = match kind.open() {
+        Ok(prober) => prober,
+        Err(e) => {
+            error!(
+                "Couldn't open {:?} prober for target {} ({})",
+                kind, target, e
+            );
+            let _ = event_tx_rcv.send(AppEvent::Error(target, AppError::Socket(e)));
+            return;
+        }
+    };
+    let prober_rcv = prober_snd.clone();
+    let event_tx_snd = event_tx_rcv.clone();
+    let stopped_snd = Arc::new(AtomicBool::new(false));
+    let stopped_rcv = stopped_snd.clone();
+
+    // Sender thread
+    thread::spawn(move || {
+        let prober = prober_snd;
+        let event_tx = event_tx_snd;
+        let mut settings = LatGraphSettings::default();
+        let mut remote_host = String::new();
+        let mut new_settings = false;
+        // When the host couldn't be resolved, time of the next attempt and the delay before it
+        let mut retry: Option<(Instant, Duration)> = None;
+        let mut valid_remote = false; // Whether we managed to ever send a ping to the current remote
+        let mut next_ping = Instant::now();
+        let mut ping_id = 0u64;
+        if let Err(e) = ThreadPriority::Max.set_for_current() {
+            warn!("Couldn't set thread priority : {:?}", e);
+        }
+
+        'main: loop {
+            if let (true, Some((retry_at, delay))) = (settings.running, retry) {
+                // Wait before resolving the host again, unless the settings change in the meantime
+                match settings_rx.recv_timeout(retry_at.saturating_duration_since(Instant::now())) {
+                    Ok(set) => {
+                        settings = set;
+                        new_settings = true;
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        info!("SND {}: Retrying to connect to {}", target, remote_host);
+                        retry = match prober.connect(&remote_host) {
+                            Ok(()) => {
+                                if event_tx.send(AppEvent::Connected(target)).is_err() {
+                                    break;
+                                }
+                                None
+                            }
+                            Err(e) => {
+                                let delay = (delay * 2).min(RESOLVE_RETRY_MAX);
+                                error!(
+                                    "SND {}: Couldn't connect to host ({}), retrying in {:?}",
+                                    target, e, delay
+                                );
+                                if event_tx
+                                    .send(AppEvent::Error(target, AppError::HostResolution(e)))
+                                    .is_err()
+                                {
+                                    break;
+                                }
+                                Some((Instant::now() + delay, delay))
+                            }
+                        };
+                    }
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+            } else if settings.running {
+                debug!("SND {}: Sending ping", target);
+                let now = Instant::now();
+                if event_tx.send(AppEvent::Ping(target, now)).is_err() {
+                    break;
+                }
+                if let Err(e) = prober.send(ping_id) {
+                    warn!(
+                        "SND {}: Couldn't send ping ({}), attempting reconnect",
+                        target, e
+                    );
+
+                    if let Err(e) = prober
+                        .connect(&remote_host)
+                        .and_then(|_| prober.send(ping_id))
+                    {
+                        next_ping += Duration::from_secs(3);
+                        if valid_remote {
+                            // If we could send a ping to the host at least once, keep trying again
+                            error!("SND {}: Reconnect failed ({}), waiting 3s", target, e);
+                        } else {
+                            // Otherwise return a host resolution error and retry later
+                            error!(
+                                "SND {}: Reconnect failed ({}), retrying in {:?}",
+                                target, e, RESOLVE_RETRY_MIN
+                            );
+                            if event_tx
+                                .send(AppEvent::Error(target, AppError::HostResolution(e)))
+                                .is_err()
+                            {
+                                break;
+                            }
+                            retry = Some((Instant::now() + RESOLVE_RETRY_MIN, RESOLVE_RETRY_MIN));
+                        }
+                    }
+                } else {
+                    valid_remote = true;
+                }
+                ping_id += 1;
+                next_ping += settings.delay;
+                if next_ping < Instant::now() {
+                    // If we're already past the next ping (process lagged a lot, computer went to sleep, etc),
+                    next_ping = Instant::now() + settings.delay;
+                }
+                thread::sleep(next_ping - Instant::now());
+
+                // Poll for new settings, looping in case there's multiple values queued
+                loop {
+                    match settings_rx.try_recv() {
+                        Ok(set) => {
+                            settings = set;
+                            new_settings = true;
+                        }
+                        Err(mpsc::TryRecvError::Empty) => break,
+                        Err(mpsc::TryRecvError::Disconnected) => break 'main,
+                    }
+                }
+            } else {
+                match settings_rx.recv() {
+                    Ok(set) => settings = set,
+                    Err(_) => break, // Main thread is probably shutting down, just exit
+                }
+                new_settings = true;
+            }
+
+            if new_settings {
+                debug!("SND {}: Received new settings {:#?}", target, settings);
+                new_settings = false;
+
+                let host = match settings.targets.first() {
+                    Some(t) => t.host.clone(),
+                    None => break,
+                };
+                prober.reconfigure(&settings);
+
+                // If remote host settings have changed
+                if host != remote_host && !host.is_empty() {
+                    valid_remote = false;
+                    info!("SND {}: Connecting to new host {}", target, host);
+                    let event = match prober.connect(&host) {
+                        Ok(()) => {
+                            retry = None;
+                            AppEvent::Connected(target)
+                        }
+                        Err(e) => {
+                            error!(
+                                "SND {}: Couldn't connect to host ({}), retrying in {:?}",
+                                target, e, RESOLVE_RETRY_MIN
+                            );
+                            retry = Some((Instant::now() + RESOLVE_RETRY_MIN, RESOLVE_RETRY_MIN));
+                            AppEvent::Error(target, AppError::HostResolution(e))
+                        }
+                    };
+                    if event_tx.send(event).is_err() {
+                        break;
+                    }
+                }
+                remote_host = host;
+
+                settings.running &= !remote_host.is_empty();
+            }
+        }
+        stopped_snd.store(true, Ordering::Relaxed);
+        debug!("SND {}: Stopping send thread", target);
+    });
+
+    // Receiver thread
+    thread::spawn(move || {
+        let prober = prober_rcv;
+        let event_tx = event_tx_rcv;
+        if let Err(e) = ThreadPriority::Max.set_for_current() {
+            warn!("Couldn't set thread priority : {:?}", e);
+        }
+
+        while !stopped_rcv.load(Ordering::Relaxed) {
+            let event = match prober.recv() {
+                Ok(Response::Pong(id, time)) => {
+                    debug!("RCV {}: Received ping {}", target, id);
+                    AppEvent::Pong(target, id, time)
+                }
+                Ok(Response::Invalid) => AppEvent::InvalidPacket(target),
+                Err(e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    continue
+                }
+                Err(e) => {
+                    debug!("RCV {}: Got err on receiver thread : {}", target, e);
+                    continue;
+                }
+            };
+            if event_tx.send(event).is_err() {
+                break;
+            }
+        }
+        debug!("RCV {}: Stopping receiver thread", target);
+    });
+}
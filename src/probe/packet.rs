@@ -0,0 +1,184 @@
+use std::{
+    collections::hash_map::RandomState,
+    convert::TryInto,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/*
+    Layout of an echo packet, all integers in network byte order:
+     0..4   magic bytes "LGPH"
+     4      format version
+     5..9   session nonce, random for each network thread
+     9..17  sequence number
+    17..25  send timestamp, in microseconds since the UNIX epoch
+    25..    padding up to the configured payload size, each byte being the
+            sum of the sequence number's low byte and its offset in the padding
+*/
+pub const MAGIC: [u8; 4] = *b"LGPH";
+pub const VERSION: u8 = 1;
+pub const HEADER_LEN: usize = 25;
+/// Largest payload that fits in a UDP datagram over IPv4
+pub const MAX_PAYLOAD_SIZE: usize = 65507;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Packet {
+    pub nonce: u32,
+    pub seq: u64,
+    pub timestamp: u64,
+    /// Total size of the packet, including padding
+    pub size: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PacketError {
+    Length(usize),
+    Magic,
+    Version(u8),
+    Nonce(u32),
+    Padding,
+}
+
+impl Packet {
+    /// Creates a packet for the given sequence number, timestamped with the current time.
+    /// The size is clamped between the header length and the maximum UDP payload size
+    pub fn new(nonce: u32, seq: u64, size: usize) -> Packet {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or(0);
+        Packet {
+            nonce,
+            seq,
+            timestamp,
+            size: size.clamp(HEADER_LEN, MAX_PAYLOAD_SIZE),
+        }
+    }
+
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.size);
+        buf.extend_from_slice(&MAGIC);
+        buf.push(VERSION);
+        buf.extend_from_slice(&self.nonce.to_be_bytes());
+        buf.extend_from_slice(&self.seq.to_be_bytes());
+        buf.extend_from_slice(&self.timestamp.to_be_bytes());
+        buf.extend((0..self.size - HEADER_LEN).map(|i| padding_byte(self.seq, i)));
+        buf
+    }
+
+    /// Parses and validates a received packet, which must belong to the session with the given
+    /// nonce and have the expected size
+    pub fn parse(buf: &[u8], nonce: u32, size: usize) -> Result<Packet, PacketError> {
+        if buf.len() < HEADER_LEN || buf.len() != size {
+            return Err(PacketError::Length(buf.len()));
+        }
+        if buf[0..4] != MAGIC {
+            return Err(PacketError::Magic);
+        }
+        if buf[4] != VERSION {
+            return Err(PacketError::Version(buf[4]));
+        }
+        let packet = Packet {
+            nonce: u32::from_be_bytes(buf[5..9].try_into().unwrap()),
+            seq: u64::from_be_bytes(buf[9..17].try_into().unwrap()),
+            timestamp: u64::from_be_bytes(buf[17..25].try_into().unwrap()),
+            size,
+        };
+        if packet.nonce != nonce {
+            return Err(PacketError::Nonce(packet.nonce));
+        }
+        let padding_valid = buf[HEADER_LEN..]
+            .iter()
+            .enumerate()
+            .all(|(i, b)| *b == padding_byte(packet.seq, i));
+        if !padding_valid {
+            return Err(PacketError::Padding);
+        }
+        Ok(packet)
+    }
+}
+
+fn padding_byte(seq: u64, offset: usize) -> u8 {
+    (seq as u8).wrapping_add(offset as u8)
+}
+
+/// Generates a random nonce identifying the packets of a session
+pub fn session_nonce() -> u32 {
+    // RandomState is seeded randomly, which saves us from depending on a RNG crate
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0),
+    );
+    hasher.finish() as u32
+}
+
+impl fmt::Display for PacketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PacketError::Length(len) => write!(f, "invalid length ({} bytes)", len),
+            PacketError::Magic => write!(f, "invalid magic bytes"),
+            PacketError::Version(v) => write!(f, "unsupported version {}", v),
+            PacketError::Nonce(n) => write!(f, "unknown session nonce {:08x}", n),
+            PacketError::Padding => write!(f, "padding doesn't match what was sent"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let packet = Packet::new(0xdead_beef, 300, 64);
+        let bytes = packet.to_bytes();
+        assert_eq!(bytes.len(), 64);
+        assert_eq!(&bytes[0..4], b"LGPH");
+        assert_eq!(Packet::parse(&bytes, 0xdead_beef, 64), Ok(packet));
+    }
+
+    #[test]
+    fn size_is_clamped() {
+        assert_eq!(Packet::new(1, 0, 0).size, HEADER_LEN);
+        assert_eq!(Packet::new(1, 0, usize::MAX).size, MAX_PAYLOAD_SIZE);
+        let bytes = Packet::new(1, 0, 0).to_bytes();
+        assert!(Packet::parse(&bytes, 1, HEADER_LEN).is_ok());
+    }
+
+    #[test]
+    fn rejects_wrong_nonce() {
+        let bytes = Packet::new(1, 5, 32).to_bytes();
+        assert_eq!(Packet::parse(&bytes, 2, 32), Err(PacketError::Nonce(1)));
+    }
+
+    #[test]
+    fn rejects_short_buffers() {
+        let bytes = Packet::new(1, 5, 32).to_bytes();
+        assert_eq!(
+            Packet::parse(&bytes[..20], 1, 20),
+            Err(PacketError::Length(20))
+        );
+        assert_eq!(
+            Packet::parse(&bytes[..31], 1, 32),
+            Err(PacketError::Length(31))
+        );
+    }
+
+    #[test]
+    fn rejects_corrupted_packets() {
+        let mut bytes = Packet::new(1, 5, 32).to_bytes();
+        bytes[HEADER_LEN + 3] ^= 0xff;
+        assert_eq!(Packet::parse(&bytes, 1, 32), Err(PacketError::Padding));
+        bytes[4] = VERSION + 1;
+        assert_eq!(
+            Packet::parse(&bytes, 1, 32),
+            Err(PacketError::Version(VERSION + 1))
+        );
+        bytes[0] = b'X';
+        assert_eq!(Packet::parse(&bytes, 1, 32), Err(PacketError::Magic));
+    }
+}
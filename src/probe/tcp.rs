@@ -0,0 +1,125 @@
+use super::{Prober, Response, RECV_TIMEOUT};
+use std::{
+    io,
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::*;
+
+pub const DEFAULT_PORT: u16 = 80;
+/// Connection attempts taking longer than this are considered lost
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Most connection attempts in progress at once, pings sent while that many are pending are lost
+const MAX_CONNECTIONS: usize = 64;
+
+/// Times TCP handshakes, opening a new connection for each ping. A refused connection counts as
+/// a response since a RST is as good as a SYN-ACK to measure the round trip, nothing is reported
+/// on timeout.
+pub struct TcpProber {
+    addr: Mutex<Option<SocketAddr>>,
+    /// Connection attempts handed to the worker threads, which are started as needed
+    jobs_tx: Mutex<mpsc::Sender<(u64, SocketAddr)>>,
+    jobs_rx: Arc<Mutex<mpsc::Receiver<(u64, SocketAddr)>>>,
+    workers: AtomicUsize,
+    /// Connection attempts queued or in progress
+    pending: Arc<AtomicUsize>,
+    // Worker threads report the time at which their handshake completed through this channel
+    responses_tx: Mutex<mpsc::Sender<(u64, Instant)>>,
+    responses_rx: Mutex<mpsc::Receiver<(u64, Instant)>>,
+}
+
+impl TcpProber {
+    pub fn new() -> TcpProber {
+        let (jobs_tx, jobs_rx) = mpsc::channel();
+        let (responses_tx, responses_rx) = mpsc::channel();
+        TcpProber {
+            addr: Mutex::new(None),
+            jobs_tx: Mutex::new(jobs_tx),
+            jobs_rx: Arc::new(Mutex::new(jobs_rx)),
+            workers: AtomicUsize::new(0),
+            pending: Arc::new(AtomicUsize::new(0)),
+            responses_tx: Mutex::new(responses_tx),
+            responses_rx: Mutex::new(responses_rx),
+        }
+    }
+
+    /// Starts a thread making the queued connection attempts, until the prober is dropped
+    fn start_worker(&self) {
+        let jobs_rx = self.jobs_rx.clone();
+        let pending = self.pending.clone();
+        let responses_tx = self.responses_tx.lock().unwrap().clone();
+        thread::spawn(move || loop {
+            let job = jobs_rx.lock().unwrap().recv();
+            let (seq, addr) = match job {
+                Ok(job) => job,
+                Err(_) => break,
+            };
+            match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+                Ok(_) => {
+                    let _ = responses_tx.send((seq, Instant::now()));
+                }
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                    let _ = responses_tx.send((seq, Instant::now()));
+                }
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                    debug!("TCP connection to {} timed out", addr)
+                }
+                Err(e) => warn!("TCP connection to {} failed ({})", addr, e),
+            }
+            pending.fetch_sub(1, Ordering::Relaxed);
+        });
+    }
+}
+
+impl Prober for TcpProber {
+    /// Resolves the host's address, using the default port if it doesn't specify one
+    fn connect(&self, host: &str) -> io::Result<()> {
+        let mut addrs = if host.contains(':') {
+            host.to_socket_addrs()?
+        } else {
+            (host, DEFAULT_PORT).to_socket_addrs()?
+        };
+        let addr = addrs
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address for host"))?;
+        *self.addr.lock().unwrap() = Some(addr);
+        Ok(())
+    }
+
+    fn send(&self, seq: u64) -> io::Result<()> {
+        let addr = self
+            .addr
+            .lock()
+            .unwrap()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "host not resolved"))?;
+        let pending = self.pending.fetch_add(1, Ordering::Relaxed) + 1;
+        if pending > MAX_CONNECTIONS {
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+            warn!(
+                "Already {} TCP connections pending to {}, skipping ping {}",
+                MAX_CONNECTIONS, addr, seq
+            );
+            return Ok(());
+        }
+        if pending > self.workers.load(Ordering::Relaxed) {
+            self.workers.fetch_add(1, Ordering::Relaxed);
+            self.start_worker();
+        }
+        // The workers only stop once the prober is dropped
+        let _ = self.jobs_tx.lock().unwrap().send((seq, addr));
+        Ok(())
+    }
+
+    fn recv(&self) -> io::Result<Response> {
+        match self.responses_rx.lock().unwrap().recv_timeout(RECV_TIMEOUT) {
+            Ok((seq, time)) => Ok(Response::Pong(seq, time)),
+            Err(_) => Err(io::ErrorKind::TimedOut.into()),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use super::{
+    packet::{self, Packet},
+    Prober, Response, RECV_TIMEOUT,
+};
+use crate::app::LatGraphSettings;
+use std::{
+    io,
+    net::UdpSocket,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use log::*;
+
+pub const DEFAULT_PORT: u16 = 7;
+
+/// Sends packets to a UDP Echo server (RFC 862), expecting them to be sent back unchanged
+pub struct UdpProber {
+    socket: UdpSocket,
+    nonce: u32,
+    payload_size: AtomicUsize,
+    /// Only used by the receiver thread, large enough for the responses to pings of any size
+    recv_buf: Mutex<Vec<u8>>,
+}
+
+impl UdpProber {
+    pub fn new() -> io::Result<UdpProber> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.set_read_timeout(Some(RECV_TIMEOUT))?;
+        Ok(UdpProber {
+            socket,
+            nonce: packet::session_nonce(),
+            payload_size: AtomicUsize::new(packet::HEADER_LEN),
+            recv_buf: Mutex::new(vec![0u8; packet::MAX_PAYLOAD_SIZE]),
+        })
+    }
+}
+
+impl Prober for UdpProber {
+    fn connect(&self, host: &str) -> io::Result<()> {
+        if host.contains(':') {
+            self.socket.connect(host)
+        } else {
+            self.socket.connect((host, DEFAULT_PORT))
+        }
+    }
+
+    fn reconfigure(&self, settings: &LatGraphSettings) {
+        let size = settings
+            .payload_size
+            .clamp(packet::HEADER_LEN, packet::MAX_PAYLOAD_SIZE);
+        self.payload_size.store(size, Ordering::Relaxed);
+    }
+
+    fn send(&self, seq: u64) -> io::Result<()> {
+        let size = self.payload_size.load(Ordering::Relaxed);
+        self.socket
+            .send(&Packet::new(self.nonce, seq, size).to_bytes())
+            .map(|_| ())
+    }
+
+    fn recv(&self) -> io::Result<Response> {
+        let mut buf = self.recv_buf.lock().unwrap();
+        let len = self.socket.recv(&mut buf)?;
+        let now = Instant::now();
+        // The payload size may have changed since the ping was sent, so the response is checked
+        // against its own length rather than the current size
+        match Packet::parse(&buf[..len], self.nonce, len) {
+            Ok(packet) => Ok(Response::Pong(packet.seq, now)),
+            Err(e) => {
+                warn!("Dropping invalid UDP packet: {}", e);
+                Ok(Response::Invalid)
+            }
+        }
+    }
+}
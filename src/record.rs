@@ -0,0 +1,339 @@
+use crate::{
+    app::{AppEvent, LatGraphSettings, TargetId, TargetSettings},
+    probe::{EventSink, ProbeKind},
+};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::*;
+
+/*
+    Recordings are CSV files with one line per record, whose first field gives its type:
+    start,<start time in microseconds since the UNIX epoch>
+    target,<index>,<probe>://<host>,<label, with backslashes and line breaks escaped>
+    sent,<target index>,<sequence number>,<microseconds since start>
+    received,<target index>,<sequence number>,<microseconds since start>
+*/
+
+/// Recorded events are flushed to the file at least this often
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// When seeking, events older than this before the new position are skipped
+const SEEK_PRELOAD: Duration = Duration::from_secs(300);
+/// Delay between two updates of the replay status
+const STATUS_INTERVAL: Duration = Duration::from_millis(500);
+/// Pings whose response we're still awaiting are forgotten after this long
+const PENDING_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Writes every ping sent and response received to a file
+#[derive(Debug)]
+pub struct Recorder {
+    writer: Option<BufWriter<File>>,
+    start: Instant,
+    last_flush: Instant,
+}
+
+#[derive(Debug)]
+pub struct Recording {
+    pub targets: Vec<TargetSettings>,
+    /// Wall-clock time at which the recording started
+    pub start: SystemTime,
+    records: Vec<Record>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Record {
+    kind: RecordKind,
+    target: usize,
+    seq: u64,
+    /// Time since the start of the recording
+    time: Duration,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum RecordKind {
+    Sent,
+    Received,
+}
+
+#[derive(Debug)]
+pub enum ReplayCommand {
+    TogglePause,
+    Rewind(Duration),
+    Forward(Duration),
+}
+
+impl Recorder {
+    pub fn create(path: &Path, settings: &LatGraphSettings) -> io::Result<Recorder> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let start_micros = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_micros())
+            .unwrap_or(0);
+        writeln!(writer, "start,{}", start_micros)?;
+        for (i, target) in settings.targets.iter().enumerate() {
+            writeln!(
+                writer,
+                "target,{},{}://{},{}",
+                i,
+                target.probe.scheme(),
+                target.host,
+                escape(&target.label)
+            )?;
+        }
+        info!("Recording to {:?}", path);
+        Ok(Recorder {
+            writer: Some(writer),
+            start: Instant::now(),
+            last_flush: Instant::now(),
+        })
+    }
+
+    pub fn sent(&mut self, target: usize, seq: u64, time: Instant) {
+        self.write(RecordKind::Sent, target, seq, time);
+    }
+
+    pub fn received(&mut self, target: usize, seq: u64, time: Instant) {
+        self.write(RecordKind::Received, target, seq, time);
+    }
+
+    fn write(&mut self, kind: RecordKind, target: usize, seq: u64, time: Instant) {
+        if let Some(writer) = &mut self.writer {
+            let kind = match kind {
+                RecordKind::Sent => "sent",
+                RecordKind::Received => "received",
+            };
+            let micros = time.saturating_duration_since(self.start).as_micros();
+            let mut result = writeln!(writer, "{},{},{},{}", kind, target, seq, micros);
+            if result.is_ok() && self.last_flush.elapsed() >= FLUSH_INTERVAL {
+                self.last_flush = Instant::now();
+                result = writer.flush();
+            }
+            if let Err(e) = result {
+                error!("Couldn't write to the recording, stopping it ({})", e);
+                self.writer = None;
+            }
+        }
+    }
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> io::Result<Recording> {
+        let invalid = |line: usize| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid recording at line {}", line + 1),
+            )
+        };
+        let mut recording = Recording {
+            targets: Vec::new(),
+            start: UNIX_EPOCH,
+            records: Vec::new(),
+        };
+        for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            let fields: Vec<&str> = line.splitn(4, ',').collect();
+            match fields[..] {
+                ["start", micros] => {
+                    let micros = micros.parse().map_err(|_| invalid(i))?;
+                    recording.start = UNIX_EPOCH + Duration::from_micros(micros);
+                }
+                ["target", _, host, label] => {
+                    // Hosts without a scheme use the default probe, as on the command line
+                    let (probe, host) = match host.find("://") {
+                        Some(pos) => (
+                            ProbeKind::from_scheme(&host[..pos]).ok_or_else(|| invalid(i))?,
+                            &host[pos + 3..],
+                        ),
+                        None => (ProbeKind::default(), host),
+                    };
+                    recording.targets.push(TargetSettings {
+                        host: String::from(host),
+                        label: unescape(label),
+                        probe,
+                        ..TargetSettings::default()
+                    })
+                }
+                [kind, target, seq, micros] => {
+                    let kind = match kind {
+                        "sent" => RecordKind::Sent,
+                        "received" => RecordKind::Received,
+                        _ => return Err(invalid(i)),
+                    };
+                    let record = Record {
+                        kind,
+                        target: target.parse().map_err(|_| invalid(i))?,
+                        seq: seq.parse().map_err(|_| invalid(i))?,
+                        time: Duration::from_micros(micros.parse().map_err(|_| invalid(i))?),
+                    };
+                    if record.target >= recording.targets.len() {
+                        return Err(invalid(i));
+                    }
+                    recording.records.push(record);
+                }
+                [""] => {}
+                _ => return Err(invalid(i)),
+            }
+        }
+        // Responses are written when received, which may be slightly out of order
+        recording.records.sort_by_key(|r| r.time);
+        Ok(recording)
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.records.last().map_or(Duration::ZERO, |r| r.time)
+    }
+
+    /// Feeds the recording to the event sink in real time, as if its events were happening now,
+    /// from a new thread controlled with the command channel
+    pub fn replay<S: EventSink>(self, commands: mpsc::Receiver<ReplayCommand>, events: S) {
+        thread::spawn(move || {
+            let duration = self.duration();
+            let records = self.records;
+            let mut pos = 0; // Index of the next record to emit
+            let mut clock = Duration::ZERO; // Current position in the recording
+            let mut clock_origin = Instant::now(); // Time at which the position was 0, while playing
+            let mut paused = false;
+            let mut next_status = Instant::now();
+            // Pings are renumbered as they're emitted since some may be skipped when seeking
+            let mut nb_sent = vec![0u64; self.targets.len()];
+            // Emitted pings awaiting a response: (target, recorded seq) => (emitted seq, time emitted at, recorded time)
+            let mut pending: HashMap<(usize, u64), (u64, Instant, Duration)> = HashMap::new();
+
+            loop {
+                let now = Instant::now();
+                if !paused {
+                    clock = now.saturating_duration_since(clock_origin);
+                }
+                while pos < records.len() && records[pos].time <= clock {
+                    let record = records[pos];
+                    pos += 1;
+                    // The targets of a replay can't be changed, their ids are their indices
+                    let target = TargetId(record.target);
+                    let event = match record.kind {
+                        RecordKind::Sent => {
+                            let time = instant_before(now, clock - record.time);
+                            let seq = nb_sent[record.target];
+                            nb_sent[record.target] += 1;
+                            pending.insert((record.target, record.seq), (seq, time, record.time));
+                            AppEvent::Ping(target, time)
+                        }
+                        RecordKind::Received => {
+                            match pending.remove(&(record.target, record.seq)) {
+                                // Offset from the ping's time so the latency stays the same even if
+                                // we paused or seeked in between
+                                Some((seq, ping_time, ping_rec_time)) => AppEvent::Pong(
+                                    target,
+                                    seq,
+                                    ping_time + record.time.saturating_sub(ping_rec_time),
+                                ),
+                                None => continue,
+                            }
+                        }
+                    };
+                    if events.send(event).is_err() {
+                        return;
+                    }
+                }
+
+                if now >= next_status {
+                    next_status = now + STATUS_INTERVAL;
+                    pending.retain(|_, (_, _, time)| *time + PENDING_TIMEOUT > clock);
+                    if events.send(AppEvent::ReplayStatus(clock, paused)).is_err() {
+                        return;
+                    }
+                }
+
+                let mut timeout = next_status.saturating_duration_since(now);
+                if !paused && pos < records.len() {
+                    timeout = timeout.min(records[pos].time.saturating_sub(clock));
+                }
+                let seek_to = match commands.recv_timeout(timeout) {
+                    Ok(ReplayCommand::TogglePause) => {
+                        paused = !paused;
+                        clock_origin = instant_before(now, clock);
+                        next_status = now;
+                        continue;
+                    }
+                    Ok(ReplayCommand::Rewind(by)) => clock.saturating_sub(by),
+                    Ok(ReplayCommand::Forward(by)) => (clock + by).min(duration),
+                    Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                };
+
+                debug!("Replay: seeking from {:?} to {:?}", clock, seek_to);
+                if seek_to < clock {
+                    // We can't remove pings from the graph, so we start over from scratch
+                    if events.send(AppEvent::ReplayReset).is_err() {
+                        return;
+                    }
+                    pos = 0;
+                    nb_sent.iter_mut().for_each(|n| *n = 0);
+                    pending.clear();
+                }
+                let preload_start = seek_to.saturating_sub(SEEK_PRELOAD);
+                pos = pos.max(records.partition_point(|r| r.time < preload_start));
+                clock = seek_to;
+                clock_origin = instant_before(now, clock);
+                next_status = now;
+            }
+        });
+    }
+}
+
+/// Escapes the characters that would break the line a value is written on
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Instant that was the given duration ago, or now if that's before the monotonic clock's origin
+fn instant_before(now: Instant, duration: Duration) -> Instant {
+    now.checked_sub(duration).unwrap_or(now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_round_trip() {
+        for label in [
+            "plain",
+            "with,comma",
+            "two\nlines\r\n",
+            "back\\slash\\n",
+            "\\",
+        ] {
+            let escaped = escape(label);
+            assert!(!escaped.contains('\n') && !escaped.contains('\r'));
+            assert_eq!(unescape(&escaped), label);
+        }
+    }
+}
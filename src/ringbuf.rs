@@ -1,20 +1,28 @@
 #![allow(dead_code)]
 use std::marker::PhantomData;
-use std::{cmp::min, convert::TryFrom, iter::Iterator, time::Instant};
+use std::{
+    convert::TryFrom,
+    iter::Iterator,
+    time::{Duration, Instant, SystemTime},
+};
 
 use log::{debug, warn};
 
 #[derive(Copy, Clone, Debug)]
 pub enum Ping {
     Sent(Instant),
-    Received(Instant, u128),
+    Received(Instant, Duration),
 }
 
 #[derive(Debug)]
 pub struct RingBuffer {
     data: Vec<Ping>,
     start_index: usize,
+    /// Position in `data` of the ping at `start_index`
+    head: usize,
     capacity: usize,
+    /// Wall-clock time of an instant, from which those of the pings are derived
+    anchor: (Instant, SystemTime),
 }
 
 pub struct RingBufferIter<'a, T> {
@@ -29,7 +37,24 @@ impl RingBuffer {
         RingBuffer {
             data: Vec::with_capacity(size),
             start_index: 0,
+            head: 0,
             capacity: size,
+            anchor: (Instant::now(), SystemTime::now()),
+        }
+    }
+
+    /// Sets the wall-clock time of the given instant, e.g when replaying pings from the past
+    pub fn set_anchor(&mut self, instant: Instant, wall_time: SystemTime) {
+        self.anchor = (instant, wall_time);
+    }
+
+    /// Wall-clock time at which the given instant happened
+    pub fn wall_time(&self, time: Instant) -> SystemTime {
+        let (instant, wall_time) = self.anchor;
+        if time >= instant {
+            wall_time + (time - instant)
+        } else {
+            wall_time - (instant - time)
         }
     }
 
@@ -48,41 +73,64 @@ impl RingBuffer {
         self.capacity
     }
 
+    /// Changes the number of pings kept, dropping the oldest ones if there are too many
+    pub fn resize(&mut self, capacity: usize) {
+        let capacity = capacity.max(1);
+        if capacity == self.capacity {
+            return;
+        }
+        let mut data = self.get_data();
+        let excess = data.len().saturating_sub(capacity);
+        data.drain(..excess);
+        data.reserve_exact(capacity - data.len());
+        debug!(
+            "Resizing ring buffer from {} to {} pings",
+            self.capacity, capacity
+        );
+        self.data = data;
+        self.start_index += excess;
+        self.head = 0;
+        self.capacity = capacity;
+    }
+
     pub fn sent(&mut self, time: Instant) {
         if self.data.len() < self.capacity {
             self.data.push(Ping::Sent(time));
         } else {
-            let i = self.start_index + self.data.len();
-            self.data[i % self.capacity] = Ping::Sent(time);
+            self.data[self.head] = Ping::Sent(time);
+            self.head = (self.head + 1) % self.capacity;
             self.start_index += 1;
         }
     }
 
-    pub fn received(&mut self, id: u64, rcv_time: Instant) {
-        let id_usize = usize::try_from(id).unwrap();
+    /// Records the response to the given ping, returning its latency if it was awaiting one
+    pub fn received(&mut self, id: u64, rcv_time: Instant) -> Option<Duration> {
+        let id_usize = usize::try_from(id).unwrap_or(usize::MAX);
         if id_usize >= self.start_index + self.data.len() {
-            panic!("Received a ping we haven't sent yet 👻");
+            warn!(
+                "Received a ping we haven't sent yet 👻 (id {}), ignoring",
+                id
+            );
         } else if id_usize >= self.start_index {
-            match self.data[id_usize % self.capacity] {
+            match self[id_usize] {
                 Ping::Sent(snd_time) => {
-                    let lat = rcv_time.saturating_duration_since(snd_time).as_millis();
-                    debug!("Received pong, latency: {}", lat);
-                    self.data[id_usize % self.capacity] = Ping::Received(snd_time, lat);
+                    let lat = rcv_time.saturating_duration_since(snd_time);
+                    debug!("Received pong, latency: {:?}", lat);
+                    self[id_usize] = Ping::Received(snd_time, lat);
+                    return Some(lat);
                 }
                 Ping::Received(_, _) => {
                     warn!("Received duplicate response");
                 }
             }
         }
+        None
     }
 
     pub fn get_data(&self) -> Vec<Ping> {
         let mut vec = Vec::with_capacity(self.data.len());
-        vec.extend_from_slice(
-            &self.data[self.start_index % self.capacity
-                ..min(self.start_index + self.data.len(), self.capacity)],
-        );
-        vec.extend_from_slice(&self.data[..self.start_index % self.capacity]);
+        vec.extend_from_slice(&self.data[self.head..]);
+        vec.extend_from_slice(&self.data[..self.head]);
         vec
     }
 
@@ -104,6 +152,31 @@ impl RingBuffer {
         }
     }
 
+    /// Iterates backwards from the ping at the given index, inclusive
+    pub fn iter_rev_from(&self, index: usize) -> RingBufferIter<'_, Ping> {
+        RingBufferIter {
+            buf: self,
+            index: usize::min(index + 1, self.start_index + self.data.len()),
+            reverse: true,
+            iter_type: PhantomData,
+        }
+    }
+
+    /// Index of the latest ping sent at or before the given time
+    pub fn index_at(&self, time: Instant) -> Option<usize> {
+        // Pings are sent in order, so their times are sorted
+        let (mut low, mut high) = (self.start_index, self.start_index + self.data.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self[mid].sent_time() <= time {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low.checked_sub(1).filter(|&i| i >= self.start_index)
+    }
+
     /// Translates "Public" index to index in the buffer
     fn buffer_index(&self, i: usize) -> usize {
         if i < self.start_index || i - self.start_index > self.data.len() {
@@ -114,7 +187,7 @@ impl RingBuffer {
                 self.data.len()
             );
         }
-        i % self.capacity
+        (self.head + i - self.start_index) % self.capacity
     }
 }
 
@@ -147,17 +220,15 @@ impl<'a> RingBufferIter<'a, Ping> {
 impl Iterator for RingBufferIter<'_, Ping> {
     type Item = Ping;
     fn next(&mut self) -> Option<<Self as Iterator>::Item> {
-        let index =
-            if !self.reverse && !self.reverse && self.index < self.buf.start_index + self.buf.len()
-            {
-                self.index += 1;
-                Some(self.index - 1)
-            } else if self.reverse && self.index > self.buf.start_index {
-                self.index -= 1;
-                Some(self.index)
-            } else {
-                None
-            };
+        let index = if !self.reverse && self.index < self.buf.start_index + self.buf.len() {
+            self.index += 1;
+            Some(self.index - 1)
+        } else if self.reverse && self.index > self.buf.start_index {
+            self.index -= 1;
+            Some(self.index)
+        } else {
+            None
+        };
 
         index.map(|i| self.buf[i])
     }
@@ -166,17 +237,15 @@ impl Iterator for RingBufferIter<'_, Ping> {
 impl Iterator for RingBufferIter<'_, (usize, Ping)> {
     type Item = (usize, Ping);
     fn next(&mut self) -> Option<<Self as Iterator>::Item> {
-        let index =
-            if !self.reverse && !self.reverse && self.index < self.buf.start_index + self.buf.len()
-            {
-                self.index += 1;
-                Some(self.index - 1)
-            } else if self.reverse && self.index > self.buf.start_index {
-                self.index -= 1;
-                Some(self.index)
-            } else {
-                None
-            };
+        let index = if !self.reverse && self.index < self.buf.start_index + self.buf.len() {
+            self.index += 1;
+            Some(self.index - 1)
+        } else if self.reverse && self.index > self.buf.start_index {
+            self.index -= 1;
+            Some(self.index)
+        } else {
+            None
+        };
 
         index.map(|i| (i, self.buf[i]))
     }
@@ -190,3 +259,93 @@ impl Ping {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Buffer of the given capacity, after sending `count` pings 10ms apart from `start`
+    fn filled(capacity: usize, count: u64, start: Instant) -> RingBuffer {
+        let mut buf = RingBuffer::new(capacity);
+        for i in 0..count {
+            buf.sent(start + Duration::from_millis(i * 10));
+        }
+        buf
+    }
+
+    fn indices(iter: RingBufferIter<'_, Ping>) -> Vec<usize> {
+        iter.with_index().map(|(i, _)| i).collect()
+    }
+
+    #[test]
+    fn wraps_around() {
+        let buf = filled(4, 6, Instant::now());
+        assert_eq!(buf.get_start_index(), 2);
+        assert_eq!(buf.get_end_index(), 5);
+        assert_eq!(indices(buf.iter()), vec![2, 3, 4, 5]);
+        assert_eq!(indices(buf.iter_rev()), vec![5, 4, 3, 2]);
+    }
+
+    #[test]
+    fn resize_wrapped_up() {
+        let start = Instant::now();
+        let mut buf = filled(4, 6, start);
+        buf.resize(8);
+        assert_eq!(buf.capacity(), 8);
+        assert_eq!(indices(buf.iter()), vec![2, 3, 4, 5]);
+        assert_eq!(buf[2].sent_time(), start + Duration::from_millis(20));
+
+        for i in 6..12 {
+            buf.sent(start + Duration::from_millis(i * 10));
+        }
+        assert_eq!(indices(buf.iter()), (4..12).collect::<Vec<_>>());
+        assert_eq!(buf[11].sent_time(), start + Duration::from_millis(110));
+    }
+
+    #[test]
+    fn resize_wrapped_down() {
+        let start = Instant::now();
+        let mut buf = filled(4, 6, start);
+        buf.resize(2);
+        assert_eq!(buf.capacity(), 2);
+        assert_eq!(indices(buf.iter()), vec![4, 5]);
+        assert_eq!(buf[4].sent_time(), start + Duration::from_millis(40));
+
+        buf.sent(start + Duration::from_millis(60));
+        assert_eq!(indices(buf.iter()), vec![5, 6]);
+        assert_eq!(buf[6].sent_time(), start + Duration::from_millis(60));
+    }
+
+    #[test]
+    fn received_updates_ping() {
+        let start = Instant::now();
+        let mut buf = filled(4, 6, start);
+        let lat = buf.received(3, start + Duration::from_millis(45));
+        assert_eq!(lat, Some(Duration::from_millis(15)));
+        assert!(matches!(buf[3], Ping::Received(_, _)));
+        // Already answered, dropped from the buffer or not sent yet
+        assert_eq!(buf.received(3, start + Duration::from_millis(50)), None);
+        assert_eq!(buf.received(1, start + Duration::from_millis(50)), None);
+        assert_eq!(buf.received(6, start + Duration::from_millis(50)), None);
+    }
+
+    #[test]
+    fn index_at() {
+        let start = Instant::now();
+        let buf = filled(4, 6, start);
+        assert_eq!(buf.index_at(start + Duration::from_millis(35)), Some(3));
+        assert_eq!(buf.index_at(start + Duration::from_millis(30)), Some(3));
+        assert_eq!(buf.index_at(start + Duration::from_millis(500)), Some(5));
+        // Before the oldest ping still in the buffer
+        assert_eq!(buf.index_at(start + Duration::from_millis(15)), None);
+        assert_eq!(RingBuffer::new(4).index_at(start), None);
+    }
+
+    #[test]
+    fn iter_rev_from() {
+        let buf = filled(4, 6, Instant::now());
+        assert_eq!(indices(buf.iter_rev_from(4)), vec![4, 3, 2]);
+        assert_eq!(indices(buf.iter_rev_from(100)), vec![5, 4, 3, 2]);
+        assert_eq!(indices(buf.iter_rev_from(1)), Vec::<usize>::new());
+    }
+}
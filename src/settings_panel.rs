@@ -0,0 +1,281 @@
+// The conrod derive macros generate their impls inside anonymous constants
+#![allow(non_local_definitions)]
+use crate::app::LatGraphSettings;
+
+use conrod_core::{
+    builder_method, color::Color, position::Dimension, widget, widget_ids, Borderable, Colorable,
+    Positionable, Sizeable, Ui, Widget, WidgetCommon, WidgetStyle,
+};
+
+/// Overlay to change the polling rate, payload size, history length and targets while running
+#[derive(Debug, WidgetCommon)]
+pub struct SettingsPanel<'a> {
+    #[conrod(common_builder)]
+    common: widget::CommonBuilder,
+    settings: &'a LatGraphSettings,
+    /// Why the last value entered couldn't be applied
+    error: Option<&'a str>,
+    /// Whether targets can be edited, added and removed
+    targets_editable: bool,
+    style: Style,
+}
+
+widget_ids!(
+    struct Ids {
+        background,
+        title,
+        labels[],
+        boxes[],
+        hint,
+        error,
+    }
+);
+
+pub struct State {
+    ids: Ids,
+    /// Text typed in each field, until it's applied with Enter
+    edits: Vec<Option<String>>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, WidgetStyle)]
+pub struct Style {
+    #[conrod(default = "theme.background_color")]
+    pub color: Option<Color>,
+    #[conrod(default = "theme.label_color")]
+    pub text_color: Option<Color>,
+
+    #[conrod(default = "1.0")]
+    pub border: Option<f64>,
+    #[conrod(default = "theme.border_color")]
+    pub border_color: Option<Color>,
+}
+
+/// A setting that can be changed from the panel
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Field {
+    Rate,
+    PayloadSize,
+    History,
+    /// Target at the given index, or a new one if past the last
+    Target(usize),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SettingsEvent {
+    /// Values entered with Enter, to be checked and applied to the settings
+    pub applied: Vec<(Field, String)>,
+    /// Whether one of the fields has the keyboard focus
+    pub editing: bool,
+}
+
+pub const PANEL_WIDTH: f64 = 460.;
+const ROW_HEIGHT: f64 = 28.;
+const MARGIN: f64 = 12.;
+const LABEL_WIDTH: f64 = 180.;
+// Space taken by the title at the top and the hint and error at the bottom
+const TITLE_HEIGHT: f64 = 28.;
+const FOOTER_HEIGHT: f64 = 48.;
+
+impl<'a> SettingsPanel<'a> {
+    pub fn new(settings: &'a LatGraphSettings) -> Self {
+        Self {
+            common: widget::CommonBuilder::default(),
+            settings,
+            error: None,
+            targets_editable: true,
+            style: Style::default(),
+        }
+    }
+
+    builder_method!(pub error { error = Option<&'a str> });
+    builder_method!(pub targets_editable { targets_editable = bool });
+    builder_method!(pub text_color { style.text_color = Some(Color) });
+
+    /// Fields shown, with their label and current value
+    fn fields(&self) -> Vec<(Field, String, String)> {
+        let mut fields = vec![
+            (
+                Field::Rate,
+                String::from("Rate (ms between pings)"),
+                self.settings.delay.as_millis().to_string(),
+            ),
+            (
+                Field::PayloadSize,
+                String::from("Payload size (bytes)"),
+                self.settings.payload_size.to_string(),
+            ),
+            (
+                Field::History,
+                String::from("History (pings or 90s, 10m, 2h)"),
+                self.settings.history.to_string(),
+            ),
+        ];
+        for (i, target) in self.settings.targets.iter().enumerate() {
+            fields.push((
+                Field::Target(i),
+                format!("Host {}", i + 1),
+                target.to_string(),
+            ));
+        }
+        if self.targets_editable {
+            let new_target = Field::Target(self.settings.targets.len());
+            fields.push((new_target, String::from("Add a host"), String::new()));
+        }
+        fields
+    }
+}
+
+impl Widget for SettingsPanel<'_> {
+    type State = State;
+    type Style = Style;
+    type Event = SettingsEvent;
+
+    fn init_state(&self, id_gen: widget::id::Generator<'_>) -> <Self as Widget>::State {
+        State {
+            ids: Ids::new(id_gen),
+            edits: Vec::new(),
+        }
+    }
+    fn style(&self) -> <Self as Widget>::Style {
+        self.style
+    }
+
+    fn default_x_dimension(&self, _ui: &Ui) -> Dimension {
+        Dimension::Absolute(PANEL_WIDTH)
+    }
+
+    fn default_y_dimension(&self, _ui: &Ui) -> Dimension {
+        let rows = self.fields().len() as f64;
+        Dimension::Absolute(TITLE_HEIGHT + rows * ROW_HEIGHT + FOOTER_HEIGHT + 2. * MARGIN)
+    }
+
+    fn update(self, args: widget::UpdateArgs<'_, '_, '_, '_, Self>) -> SettingsEvent {
+        let widget::UpdateArgs {
+            id,
+            rect,
+            state,
+            ui,
+            ..
+        } = args;
+
+        let fields = self.fields();
+        let nb_fields = fields.len();
+        if state.ids.boxes.len() < nb_fields || state.edits.len() != nb_fields {
+            state.update(|state| {
+                let id_gen = &mut ui.widget_id_generator();
+                if state.ids.boxes.len() < nb_fields {
+                    state.ids.labels.resize(nb_fields, id_gen);
+                    state.ids.boxes.resize(nb_fields, id_gen);
+                }
+                // Rows move when targets are added or removed, don't keep text in the wrong one
+                state.edits = vec![None; nb_fields];
+            });
+        }
+
+        let color = self.style.color(ui.theme());
+        let text_color = self.style.text_color(ui.theme());
+        let border_color = self.style.border_color(ui.theme());
+        widget::BorderedRectangle::new(rect.dim())
+            .xy(rect.xy())
+            .color(color)
+            .border(self.style.border(ui.theme()))
+            .border_color(border_color)
+            .parent(id)
+            .graphics_for(id)
+            .set(state.ids.background, ui);
+        widget::Text::new("Settings")
+            .top_left_with_margins_on(id, MARGIN, MARGIN)
+            .font_size(14)
+            .color(text_color)
+            .parent(id)
+            .set(state.ids.title, ui);
+
+        let mut event = SettingsEvent::default();
+        let box_w = rect.w() - LABEL_WIDTH - 2. * MARGIN;
+        for (i, (field, label, value)) in fields.into_iter().enumerate() {
+            let top = MARGIN + TITLE_HEIGHT + i as f64 * ROW_HEIGHT;
+            widget::Text::new(&label)
+                .top_left_with_margins_on(id, top + 6., MARGIN)
+                .font_size(11)
+                .color(text_color)
+                .parent(id)
+                .set(state.ids.labels[i], ui);
+            if let (Field::Target(_), false) = (field, self.targets_editable) {
+                // Shown for reference, the value can't be changed
+                widget::Text::new(&value)
+                    .top_right_with_margins_on(id, top + 6., MARGIN)
+                    .w(box_w)
+                    .font_size(11)
+                    .color(text_color.alpha(0.6))
+                    .parent(id)
+                    .set(state.ids.boxes[i], ui);
+                continue;
+            }
+            let mut text = state.edits[i].clone().unwrap_or(value);
+            let box_events = widget::TextBox::new(&text)
+                .top_right_with_margins_on(id, top, MARGIN)
+                .w_h(box_w, ROW_HEIGHT - 4.)
+                .font_size(11)
+                .color(color.highlighted())
+                .text_color(text_color)
+                .border_color(border_color)
+                .parent(id)
+                .set(state.ids.boxes[i], ui);
+            // An update and Enter can come in the same frame, the latter applies the updated text
+            for box_event in box_events {
+                match box_event {
+                    widget::text_box::Event::Update(new) => {
+                        text = new.clone();
+                        state.update(|state| state.edits[i] = Some(new))
+                    }
+                    widget::text_box::Event::Enter => {
+                        event.applied.push((field, text.clone()));
+                        state.update(|state| state.edits[i] = None);
+                    }
+                }
+            }
+        }
+
+        let hint = if self.targets_editable {
+            "Enter applies a value. Hosts are given as [label=][icmp:// or tcp://]host[:port], leave one empty to remove it. O or Escape closes."
+        } else {
+            "Enter applies a value. O or Escape closes."
+        };
+        widget::Text::new(hint)
+            .top_left_with_margins_on(
+                id,
+                MARGIN + TITLE_HEIGHT + nb_fields as f64 * ROW_HEIGHT + 4.,
+                MARGIN,
+            )
+            .w(rect.w() - 2. * MARGIN)
+            .font_size(10)
+            .color(text_color.alpha(0.6))
+            .parent(id)
+            .set(state.ids.hint, ui);
+        if let Some(error) = self.error {
+            widget::Text::new(error)
+                .bottom_left_with_margins_on(id, MARGIN, MARGIN)
+                .w(rect.w() - 2. * MARGIN)
+                .font_size(10)
+                .color(conrod_core::color::LIGHT_RED)
+                .parent(id)
+                .set(state.ids.error, ui);
+        }
+
+        event.editing = ui
+            .global_input()
+            .current
+            .widget_capturing_keyboard
+            .is_some_and(|captured| state.ids.boxes.contains(&captured));
+        event
+    }
+}
+
+impl Colorable for SettingsPanel<'_> {
+    builder_method!(color { style.color = Some(Color) });
+}
+
+impl Borderable for SettingsPanel<'_> {
+    builder_method!(border { style.border = Some(f64) });
+    builder_method!(border_color { style.border_color = Some(Color) });
+}
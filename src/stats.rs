@@ -0,0 +1,191 @@
+use crate::ringbuf::{Ping, RingBuffer};
+use std::time::{Duration, Instant};
+
+/// Pings that haven't been answered after this delay are counted as lost
+pub const LOSS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Number of latency ranges counted by a histogram
+pub const HISTOGRAM_BINS: usize = 100;
+/// Latencies spread logarithmically over the bins, in milliseconds. Those outside of it are
+/// counted in the first or last bin.
+const HISTOGRAM_RANGE_MS: (f64, f64) = (0.01, 100_000.);
+
+/// Latency and loss statistics over a series of pings
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    pub sent: u64,
+    pub received: u64,
+    pub min: Option<Duration>,
+    pub max: Option<Duration>,
+    total: Duration,
+    /// Mean deviation of the difference in latency between consecutive responses (RFC 3550)
+    pub jitter: Duration,
+    last: Option<Duration>,
+}
+
+impl Stats {
+    /// Statistics over the pings in a buffer, leaving out those still awaiting a response
+    pub fn from_buffer(buffer: &RingBuffer, now: Instant) -> Stats {
+        let mut stats = Stats::default();
+        for ping in buffer.iter() {
+            match ping {
+                Ping::Received(_, latency) => {
+                    stats.sent();
+                    stats.received(latency);
+                }
+                Ping::Sent(time) if now.saturating_duration_since(time) >= LOSS_TIMEOUT => {
+                    stats.sent()
+                }
+                Ping::Sent(_) => {}
+            }
+        }
+        stats
+    }
+
+    pub fn sent(&mut self) {
+        self.sent += 1;
+    }
+
+    pub fn received(&mut self, latency: Duration) {
+        self.received += 1;
+        self.total += latency;
+        self.min = Some(self.min.map_or(latency, |min| min.min(latency)));
+        self.max = Some(self.max.map_or(latency, |max| max.max(latency)));
+        if let Some(last) = self.last {
+            let diff = latency.abs_diff(last);
+            // J(i) = J(i-1) + (|D(i-1,i)| - J(i-1))/16
+            let jitter = self.jitter.as_secs_f64();
+            self.jitter = Duration::from_secs_f64(jitter + (diff.as_secs_f64() - jitter) / 16.);
+        }
+        self.last = Some(latency);
+    }
+
+    pub fn avg(&self) -> Option<Duration> {
+        if self.received > 0 {
+            Some(Duration::from_secs_f64(
+                self.total.as_secs_f64() / self.received as f64,
+            ))
+        } else {
+            None
+        }
+    }
+
+    pub fn lost(&self) -> u64 {
+        self.sent.saturating_sub(self.received)
+    }
+
+    /// Ratio of lost pings, between 0 and 1
+    pub fn loss(&self) -> f64 {
+        if self.sent > 0 {
+            self.lost() as f64 / self.sent as f64
+        } else {
+            0.
+        }
+    }
+}
+
+/// Number of latencies falling in each of a series of logarithmically spaced ranges
+#[derive(Clone, Debug, Default)]
+pub struct Histogram {
+    /// Allocated on the first latency added
+    counts: Vec<u32>,
+}
+
+impl Histogram {
+    pub fn add(&mut self, latency: Duration) {
+        if self.counts.is_empty() {
+            self.counts = vec![0; HISTOGRAM_BINS];
+        }
+        self.counts[Histogram::bin(latency)] += 1;
+    }
+
+    /// Number of latencies in each bin, empty if none was added
+    pub fn counts(&self) -> &[u32] {
+        &self.counts
+    }
+
+    /// Bin the given latency is counted in
+    fn bin(latency: Duration) -> usize {
+        let (min, max) = HISTOGRAM_RANGE_MS;
+        let ms = latency.as_secs_f64() * 1000.;
+        let position = (ms / min).ln() / (max / min).ln() * HISTOGRAM_BINS as f64;
+        (position.max(0.) as usize).min(HISTOGRAM_BINS - 1)
+    }
+
+    /// Lowest and highest latencies counted in the given bin
+    pub fn bin_range(bin: usize) -> (Duration, Duration) {
+        let (min, max) = HISTOGRAM_RANGE_MS;
+        let edge = |i: usize| {
+            Duration::from_secs_f64(
+                min * (max / min).powf(i as f64 / HISTOGRAM_BINS as f64) / 1000.,
+            )
+        };
+        (edge(bin), edge(bin + 1))
+    }
+}
+
+/// Value below which the given fraction of the samples fall (nearest-rank method), sorting them
+pub fn percentile(samples: &mut [Duration], fraction: f64) -> Option<Duration> {
+    if samples.is_empty() {
+        return None;
+    }
+    samples.sort_unstable();
+    let rank = (fraction * samples.len() as f64).ceil() as usize;
+    Some(samples[rank.clamp(1, samples.len()) - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(values: &[u64]) -> Vec<Duration> {
+        values.iter().map(|&v| Duration::from_millis(v)).collect()
+    }
+
+    #[test]
+    fn percentile_nearest_rank() {
+        let mut samples = ms(&[50, 10, 40, 20, 30]);
+        assert_eq!(
+            percentile(&mut samples, 0.),
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(
+            percentile(&mut samples, 0.2),
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(
+            percentile(&mut samples, 0.21),
+            Some(Duration::from_millis(20))
+        );
+        assert_eq!(
+            percentile(&mut samples, 0.5),
+            Some(Duration::from_millis(30))
+        );
+        assert_eq!(
+            percentile(&mut samples, 0.99),
+            Some(Duration::from_millis(50))
+        );
+        assert_eq!(
+            percentile(&mut samples, 1.),
+            Some(Duration::from_millis(50))
+        );
+    }
+
+    #[test]
+    fn percentile_edge_cases() {
+        assert_eq!(percentile(&mut [], 0.5), None);
+        let mut single = ms(&[7]);
+        assert_eq!(percentile(&mut single, 0.), Some(Duration::from_millis(7)));
+        assert_eq!(percentile(&mut single, 1.), Some(Duration::from_millis(7)));
+
+        let mut samples: Vec<Duration> = (1..=100).rev().map(Duration::from_millis).collect();
+        assert_eq!(
+            percentile(&mut samples, 0.95),
+            Some(Duration::from_millis(95))
+        );
+        assert_eq!(
+            percentile(&mut samples, 0.999),
+            Some(Duration::from_millis(100))
+        );
+    }
+}
@@ -1,15 +1,29 @@
+// The conrod derive macros generate their impls inside anonymous constants
+#![allow(non_local_definitions)]
+// Clippy suggests `Option::is_none_or` in place of `map_or(true, ..)`, which needs Rust 1.82
+#![allow(clippy::unnecessary_map_or)]
 use crate::{
-    app::LatGraphSettings,
+    aggregate::{Aggregates, Bucket},
+    app::{LatGraphSettings, Threshold},
     ringbuf::{Ping, RingBuffer},
+    stats::{percentile, Histogram, LOSS_TIMEOUT},
 };
 use conrod_core::Borderable;
-use std::time::{Duration, Instant};
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant, UNIX_EPOCH},
+};
 
+use chrono::{DateTime, Local, Offset, TimeZone};
 use conrod_core::{
     builder_method,
-    color::{self, Color},
+    color::{self, Color, Rgba},
     position::{range::Range, Padding},
-    widget, widget_ids, Colorable, Positionable, Rect, Sizeable, Widget, WidgetCommon, WidgetStyle,
+    widget::{
+        self,
+        triangles::{self, ColoredPoint, Triangle},
+    },
+    widget_ids, Colorable, Point, Positionable, Rect, Sizeable, Widget, WidgetCommon, WidgetStyle,
 };
 use log::*;
 
@@ -18,7 +32,13 @@ pub struct LatencyGraphWidget<'a> {
     #[conrod(common_builder)]
     common: widget::CommonBuilder,
     buffer: &'a RingBuffer,
+    aggregates: Option<&'a Aggregates>,
     settings: &'a LatGraphSettings,
+    label: Option<&'a str>,
+    /// Time at the right edge of the graph when the view is frozen, the latest pings otherwise
+    view_end: Option<Instant>,
+    /// Latency thresholds marked on the graph, in increasing order
+    thresholds: &'a [Threshold],
     style: Style,
     is_mouse_over_window: bool,
 }
@@ -26,9 +46,11 @@ pub struct LatencyGraphWidget<'a> {
 widget_ids!(
     struct Ids {
         border,
+        label,
         hover_highlight,
         x_ticks[],
         x_tick_label,
+        x_tick_labels[],
         y_ticks[],
         y_tick_label,
         y_min_tick,
@@ -38,19 +60,53 @@ widget_ids!(
         y_avg_tick,
         y_avg_label,
         y_minmax_bar,
+        percentile_ticks[],
+        percentile_labels[],
+        percentile_lines[],
+        grid_lines[],
+        grid_labels[],
+        threshold_fills[],
+        threshold_lines[],
+        threshold_labels[],
+        hover_bar,
+        tooltip,
+        tooltip_text,
         bars[],
+        plot,
+        bands[],
+        band_tops[],
+        band_avgs[],
+        band_losses[],
     }
 );
 
 const ZOOM_BASE: f64 = 1.2;
-pub const ZOOM_DEFAULT: u16 = 8;
+pub const ZOOM_DEFAULT: i16 = 8;
+// Below zero bars get narrower than a pixel, and are replaced by aggregates over several pings
+const ZOOM_MIN: f64 = -45.;
 const ZOOM_MAX: f64 = 20.;
+// The vertical scale goes further to tell sub-millisecond latencies apart
+const ZOOM_Y_MAX: f64 = 40.;
+// Latency in milliseconds drawn at the same height whatever the scale, and that height at zoom 0
+const Y_REFERENCE_MS: f64 = 100.;
+const Y_REFERENCE_PX: f64 = 20.;
+// Latencies below this, in milliseconds, are squashed together on the logarithmic scale
+const LOG_SCALE_FLOOR_MS: f64 = 0.01;
+// Fraction of the graph's height taken by the latency it's fitted to, and the lowest one it zooms to
+const FIT_HEIGHT: f64 = 0.9;
+const FIT_MIN_MS: f64 = 0.01;
+// Min distance between horizontal gridlines, in pixels
+const GRID_MIN_STEP: f64 = 25.;
+// Min width of the columns of the heatmap, and size of the dots of the scatter plot, in pixels
+const HEATMAP_COLUMN_W: f64 = 4.;
+const SCATTER_DOT_SIZE: f64 = 3.;
 // Min,max distance between horizontal ticks, in pixels
-const TICK_MIN_STEP: u128 = 75;
-const TICK_MAX_STEP: u128 = 200;
-const TICK_STEPS: [u128; 12] = [
+const TICK_MIN_STEP: f64 = 75.;
+const TICK_MAX_STEP: f64 = 200.;
+const TICK_STEPS: [u64; 19] = [
     // Allowed values for the distance in milliseconds between ticks
-    100, 250, 500, 1000, 2500, 5000, 10_000, 20_000, 30_000, 60_000, 120_000, 240_000,
+    100, 250, 500, 1000, 2500, 5000, 10_000, 20_000, 30_000, 60_000, 120_000, 240_000, 600_000,
+    1_800_000, 3_600_000, 10_800_000, 21_600_000, 43_200_000, 86_400_000,
 ];
 
 const GRAPH_AREA_PADDING: Padding = Padding {
@@ -67,9 +123,84 @@ const GRAPH_AREA_PADDING: Padding = Padding {
 pub struct State {
     ids: Ids,
     tick_step: usize, // Index of the current tick step in teh TICK_STEPS array
+    /// Latency the vertical axis was fitted to on the last update
+    fit_lat: Option<Duration>,
 }
 
-type Zoom = (u16, u16);
+pub type Zoom = (i16, i16);
+
+/// How latencies are mapped to heights on the graph
+#[cfg_attr(
+    feature = "config",
+    derive(serde_derive::Serialize, serde_derive::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum YScale {
+    Linear,
+    /// Spreads out low latencies while keeping higher ones in view
+    #[default]
+    Sqrt,
+    Log,
+}
+
+/// What sets the vertical zoom
+#[cfg_attr(
+    feature = "config",
+    derive(serde_derive::Serialize, serde_derive::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum YFit {
+    /// Scrolling over the vertical axis
+    #[default]
+    Manual,
+    /// Keeps the highest visible latency in view
+    Max,
+    /// Keeps the 99th percentile of the visible latencies in view, letting outliers go off the top
+    P99,
+}
+
+/// How the latencies are drawn
+#[cfg_attr(
+    feature = "config",
+    derive(serde_derive::Serialize, serde_derive::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum PlotStyle {
+    /// A bar per ping, or bands over groups of pings when zoomed out
+    #[default]
+    Bars,
+    /// A line through the latencies, or their averages when zoomed out
+    Line,
+    /// A dot per ping
+    Scatter,
+    /// Columns colored by how many latencies fall in each range, for long runs
+    Heatmap,
+}
+
+/// Maps latencies to heights on a graph
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct YAxis {
+    pub scale: YScale,
+    /// Heights of a null latency and of the top of the graph
+    pub bottom: f64,
+    pub top: f64,
+    /// Height of a unit of the scale
+    unit: f64,
+}
+
+/// What the user did to the graph during an update, and what it shows
+#[derive(Clone, Debug, Default)]
+pub struct GraphEvent {
+    pub zoom: Zoom,
+    /// Horizontal distance in pixels the graph was dragged to the right
+    pub drag: f64,
+    pub y_axis: YAxis,
+    /// Latencies over the visible part of the graph, or averages of them when zoomed out far
+    pub latencies: Vec<Duration>,
+}
 
 #[derive(Copy, Clone, Debug, Default, PartialEq, WidgetStyle)]
 pub struct Style {
@@ -93,31 +224,40 @@ impl<'a> LatencyGraphWidget<'a> {
         Self {
             common: widget::CommonBuilder::default(),
             buffer,
+            aggregates: None,
             settings,
+            label: None,
+            view_end: None,
+            thresholds: &[],
             style: Style::default(),
             is_mouse_over_window,
         }
     }
 
     builder_method!(pub missing_color { style.missing_color = Some(Color) });
+    builder_method!(pub label { label = Some(&'a str) });
+    builder_method!(pub aggregates { aggregates = Some(&'a Aggregates) });
+    builder_method!(pub view_end { view_end = Option<Instant> });
+    builder_method!(pub thresholds { thresholds = &'a [Threshold] });
 }
 
 impl Widget for LatencyGraphWidget<'_> {
     type State = State;
     type Style = Style;
-    type Event = Zoom;
+    type Event = GraphEvent;
 
     fn init_state(&self, id_gen: widget::id::Generator<'_>) -> <Self as Widget>::State {
         State {
             ids: Ids::new(id_gen),
             tick_step: 0,
+            fit_lat: None,
         }
     }
     fn style(&self) -> <Self as Widget>::Style {
         self.style
     }
 
-    fn update(self, args: widget::UpdateArgs<'_, '_, '_, '_, Self>) -> Zoom {
+    fn update(self, args: widget::UpdateArgs<'_, '_, '_, '_, Self>) -> GraphEvent {
         let widget::UpdateArgs {
             id,
             rect: widget_area,
@@ -172,37 +312,205 @@ impl Widget for LatencyGraphWidget<'_> {
         if delta_zoom != 0. {
             if is_over_x {
                 let old_zoom = zoom.0;
-                zoom.0 = (zoom.0 as f64 + delta_zoom).clamp(0., ZOOM_MAX) as u16;
+                zoom.0 = (zoom.0 as f64 + delta_zoom).clamp(ZOOM_MIN, ZOOM_MAX) as i16;
                 debug!("Adjusting horizontal zoom {} -> {}", old_zoom, zoom.0);
             } else if is_over_y {
                 let old_zoom = zoom.1;
-                zoom.1 = (zoom.1 as f64 + delta_zoom).clamp(0., ZOOM_MAX) as u16;
+                zoom.1 = (zoom.1 as f64 + delta_zoom).clamp(0., ZOOM_Y_MAX) as i16;
                 debug!("Adjusting vertical zoom {} -> {}", old_zoom, zoom.1);
             }
         }
+        let drag = inputs.drags().left().map(|drag| drag.delta_xy[0]).sum();
 
         /* PING BARS */
         let bar_color = self.style.color(ui.theme()).alpha(0.5);
         let missing_color = color::rgba_bytes(192, 64, 32, 0.3);
         let bar_width = f64::powi(ZOOM_BASE, zoom.0 as i32);
         let now = Instant::now();
-        let x_step = bar_width + 1.;
-        let x_offset = if self.buffer.len() > 0 && self.settings.running {
-            // Offset as a function of time since the last packet was sent
-            now.saturating_duration_since(self.buffer[self.buffer.get_end_index()].sent_time())
-                .as_micros() as f64
-                / self.settings.delay.as_micros() as f64
-        } else {
-            1.
+        let plot_style = self.settings.plot_style;
+        let aggregated = bar_width < 1. || plot_style == PlotStyle::Heatmap;
+        let x_step = x_step(bar_width);
+        // Latest ping shown, and its distance from the right edge
+        let (last_index, x_offset) = match self.view_end {
+            None if self.buffer.len() > 0 => {
+                let last_index = self.buffer.get_end_index();
+                let x_offset = if self.settings.running {
+                    // Offset as a function of time since the last packet was sent
+                    now.saturating_duration_since(self.buffer[last_index].sent_time())
+                        .as_micros() as f64
+                        / self.settings.delay.as_micros() as f64
+                } else {
+                    1.
+                };
+                (Some(last_index), bar_width * x_offset.clamp(0., 1.))
+            }
+            None => (None, bar_width),
+            Some(view_end) => match self.buffer.index_at(view_end) {
+                Some(last_index) => {
+                    let since =
+                        view_end.saturating_duration_since(self.buffer[last_index].sent_time());
+                    let x_offset = since.as_secs_f64() / self.settings.delay.as_secs_f64() * x_step;
+                    (Some(last_index), x_offset.min(graph_area.w()))
+                }
+                None => (None, 0.),
+            },
+        };
+        let nb_points = match last_index {
+            Some(last_index) if !aggregated => usize::min(
+                last_index + 1 - self.buffer.get_start_index(),
+                (graph_area.w() / x_step) as usize + 2,
+            ),
+            _ => 0,
         };
-        let x_offset = bar_width * x_offset.clamp(0., 1.);
-        let nb_points = usize::min(self.buffer.len(), (graph_area.w() / x_step) as usize + 2);
-        let mut min_lat = u128::MAX;
-        let mut max_lat = 0;
-        let mut avg_lat = 0;
+        let mut min_lat = Duration::MAX;
+        let mut max_lat = Duration::ZERO;
+        let mut avg_lat = Duration::ZERO;
         let mut nb_lat = 0;
+        // Latencies over the visible part of the graph, for the percentiles
+        let mut visible_lats = Vec::new();
+        // Whether some of them are averages of the summaries kept beyond the history
+        let mut approx_lats = false;
 
-        let lat_to_y = |lat| graph_area.bottom() + f64::sqrt(lat as f64) * f64::powi(ZOOM_BASE, zoom.1 as i32) * 2.;
+        let y_scale = self.settings.y_scale;
+        let y_unit = match (self.settings.y_fit, state.fit_lat) {
+            (YFit::Max | YFit::P99, Some(fit_lat)) => {
+                FIT_HEIGHT * graph_area.h()
+                    / y_scale.transform(f64::max(fit_lat.as_secs_f64() * 1000., FIT_MIN_MS))
+            }
+            _ => {
+                Y_REFERENCE_PX / y_scale.transform(Y_REFERENCE_MS)
+                    * f64::powi(ZOOM_BASE, zoom.1 as i32)
+            }
+        };
+        let y_axis = YAxis {
+            scale: y_scale,
+            bottom: graph_area.bottom(),
+            top: graph_area.top(),
+            unit: y_unit,
+        };
+        let ms_to_y = |ms: f64| y_axis.ms_to_y(ms);
+        let lat_to_y = |lat: Duration| y_axis.lat_to_y(lat);
+
+        /* GRIDLINES */
+        // Round latencies in milliseconds, with the number of decimals they need
+        let round_lats = (-2..=5).flat_map(|exp: i32| {
+            [1., 2., 5.].iter().map(move |&m| {
+                if exp < 0 {
+                    (m / f64::powi(10., -exp), -exp as usize)
+                } else {
+                    (m * f64::powi(10., exp), 0)
+                }
+            })
+        });
+        let mut grid = Vec::new();
+        if y_scale == YScale::Linear {
+            // Evenly spaced
+            let step = round_lats
+                .clone()
+                .find(|&(ms, _)| ms_to_y(ms) - graph_area.bottom() >= GRID_MIN_STEP);
+            if let Some((step, decimals)) = step {
+                grid.extend(
+                    (1..)
+                        .map(|i| (i as f64 * step, decimals))
+                        .take_while(|&(ms, _)| ms_to_y(ms) <= graph_area.top()),
+                );
+            }
+        } else {
+            // Spacing changes with the latency, keep those that are far enough from the previous one
+            let mut last_y = graph_area.bottom();
+            for (ms, decimals) in round_lats {
+                let y = ms_to_y(ms);
+                if y > graph_area.top() {
+                    break;
+                }
+                if y - last_y >= GRID_MIN_STEP {
+                    grid.push((ms, decimals));
+                    last_y = y;
+                }
+            }
+        }
+        if grid.len() > state.ids.grid_lines.len() {
+            state.update(|state| {
+                let id_gen = &mut ui.widget_id_generator();
+                state.ids.grid_lines.resize(grid.len(), id_gen);
+                state.ids.grid_labels.resize(grid.len(), id_gen);
+            });
+        }
+        for (i, &(ms, decimals)) in grid.iter().enumerate() {
+            let y = ms_to_y(ms);
+            widget::Line::abs([graph_area.left(), y], [graph_area.right(), y])
+                .color(border_color.alpha(0.12))
+                .parent(id)
+                .graphics_for(id)
+                .set(state.ids.grid_lines[i], ui);
+            let label = if ms >= 1000. {
+                format!("{}s", ms / 1000.)
+            } else {
+                format!("{:.*}ms", decimals, ms)
+            };
+            widget::Text::new(&label)
+                .xy([graph_area.left() + 24., y + 6.])
+                .wh([40., 10.])
+                .left_justify()
+                .font_size(8)
+                .color(border_color.alpha(0.4))
+                .parent(id)
+                .graphics_for(id)
+                .set(state.ids.grid_labels[i], ui);
+        }
+
+        /* THRESHOLDS */
+        let nb_thresholds = self.thresholds.len();
+        if state.ids.threshold_fills.len() < nb_thresholds {
+            state.update(|state| {
+                let id_gen = &mut ui.widget_id_generator();
+                state.ids.threshold_fills.resize(nb_thresholds, id_gen);
+                state.ids.threshold_lines.resize(nb_thresholds, id_gen);
+                state.ids.threshold_labels.resize(nb_thresholds, id_gen);
+            });
+        }
+        let mut from_y = graph_area.bottom();
+        for (i, threshold) in self.thresholds.iter().enumerate() {
+            let to_y = threshold
+                .below
+                .map_or(graph_area.top(), |ms| ms_to_y(ms).min(graph_area.top()));
+            if to_y > from_y {
+                let band =
+                    Rect::from_corners([graph_area.left(), from_y], [graph_area.right(), to_y]);
+                widget::Rectangle::fill(band.dim())
+                    .xy(band.xy())
+                    .color(threshold.color().alpha(0.08))
+                    .parent(id)
+                    .graphics_for(id)
+                    .set(state.ids.threshold_fills[i], ui);
+            }
+            if let Some(ms) = threshold.below.filter(|_| to_y < graph_area.top()) {
+                widget::Line::abs([graph_area.left(), to_y], [graph_area.right(), to_y])
+                    .color(threshold.color().alpha(0.6))
+                    .parent(id)
+                    .graphics_for(id)
+                    .set(state.ids.threshold_lines[i], ui);
+                widget::Text::new(&format!("{}ms", ms))
+                    .xy([graph_area.right() - 24., to_y + 6.])
+                    .wh([40., 10.])
+                    .right_justify()
+                    .font_size(8)
+                    .color(threshold.color().alpha(0.8))
+                    .parent(id)
+                    .graphics_for(id)
+                    .set(state.ids.threshold_labels[i], ui);
+            }
+            from_y = to_y;
+        }
+        // Color of the bars of the given latency, that of the graph unless colored by threshold
+        let threshold_color = |lat: Duration| {
+            let ms = lat.as_secs_f64() * 1000.;
+            self.thresholds
+                .iter()
+                .find(|t| t.below.map_or(true, |below| ms < below))
+                .filter(|_| self.settings.threshold_colors)
+                .map(Threshold::color)
+        };
 
         if state.ids.bars.len() < nb_points {
             state.update(|state| {
@@ -212,19 +520,40 @@ impl Widget for LatencyGraphWidget<'_> {
                     .resize(nb_points, &mut ui.widget_id_generator())
             });
         }
-        for (i, ping) in self.buffer.iter_rev().take(nb_points).enumerate() {
+        let mouse_xy = ui
+            .widget_input(id)
+            .mouse()
+            .map(|mouse| mouse.abs_xy())
+            .filter(|&xy| self.is_mouse_over_window && graph_area.is_over(xy));
+        let mut hovered = None;
+        // Points of the line or scatter plot, broken where pings were lost
+        let mut points: Vec<Option<Point>> = Vec::new();
+        for (i, (index, ping)) in self
+            .buffer
+            .iter_rev_from(last_index.unwrap_or(0))
+            .with_index()
+            .take(nb_points)
+            .enumerate()
+        {
             let x = graph_area.right() - (i as f64 * x_step + x_offset);
+            if mouse_xy.is_some_and(|[mx, _]| mx >= x && mx < x + x_step) {
+                hovered = Some((index, ping, x));
+            }
 
             match ping {
                 Ping::Received(_, lat) => {
+                    visible_lats.push(lat);
                     let y = lat_to_y(lat);
-                    if let Some(rct) =
+                    if plot_style != PlotStyle::Bars {
+                        points.push(Some([x + bar_width / 2., y]));
+                    } else if let Some(rct) =
                         Rect::from_corners([x, graph_area.bottom()], [x + bar_width, y])
                             .overlap(graph_area)
                     {
+                        let color = threshold_color(lat).map_or(bar_color, |c| c.alpha(0.5));
                         widget::Rectangle::fill(rct.dim())
                             .xy(rct.xy())
-                            .color(bar_color)
+                            .color(color)
                             .parent(id)
                             .graphics_for(id)
                             .set(state.ids.bars[i], ui);
@@ -239,6 +568,7 @@ impl Widget for LatencyGraphWidget<'_> {
                     nb_lat += 1;
                 }
                 Ping::Sent(time) => {
+                    points.push(None);
                     if let Some(rct) = Rect::from_corners(
                         [x, graph_area.bottom()],
                         [x + bar_width, graph_area.top()],
@@ -250,7 +580,7 @@ impl Widget for LatencyGraphWidget<'_> {
                         let alpha = alpha.clamp(0., 1.);
                         widget::Rectangle::fill(rct.dim())
                             .xy(rct.xy())
-                            .color(missing_color.clone().alpha(alpha))
+                            .color(missing_color.alpha(alpha))
                             .parent(id)
                             .graphics_for(id)
                             .set(state.ids.bars[i], ui);
@@ -263,6 +593,228 @@ impl Widget for LatencyGraphWidget<'_> {
             }
         }
 
+        // Triangles of the line, scatter or heatmap plot, drawn as a single widget
+        let mut plot: Vec<Triangle<ColoredPoint>> = Vec::new();
+
+        /* AGGREGATES */
+        if aggregated {
+            // Bars would be narrower than a pixel, draw the range of latencies over groups of pings
+            let column_w = if plot_style == PlotStyle::Heatmap {
+                HEATMAP_COLUMN_W
+            } else {
+                1.
+            };
+            let delay = self.settings.delay.as_secs_f64();
+            let end_time = match self.view_end {
+                Some(view_end) => view_end,
+                None if self.buffer.len() > 0 && !self.settings.running => {
+                    self.buffer[self.buffer.get_end_index()].sent_time() + self.settings.delay
+                }
+                None => now,
+            };
+            let time_to_x = |time: Instant| {
+                graph_area.right()
+                    - end_time.saturating_duration_since(time).as_secs_f64() / delay * x_step
+            };
+            let tiers = self.aggregates.map_or(&[][..], |a| a.tiers());
+            let buffer_start = self.buffer.iter().next().map(|ping| ping.sent_time());
+            let (buckets, bucket_w, from_tiers) = if buffer_start
+                .is_some_and(|start| time_to_x(start) <= graph_area.left())
+                || self.buffer.get_start_index() == 0
+                || tiers.is_empty()
+            {
+                // The buffer covers the whole graph, group its pings on fixed indices so that
+                // the groups don't change as new pings come in
+                let group = (column_w / x_step).ceil() as usize;
+                let group_w = group as f64 * x_step;
+                let mut buckets = Vec::new();
+                let mut current: Option<(usize, Bucket)> = None;
+                for (i, ping) in self.buffer.iter_rev().with_index() {
+                    if ping.sent_time() > end_time {
+                        continue;
+                    }
+                    if current.as_ref().map_or(true, |(g, _)| *g != i / group) {
+                        if let Some((_, mut bucket)) = current.take() {
+                            bucket.finish();
+                            if time_to_x(bucket.start) + group_w < graph_area.left() {
+                                break;
+                            }
+                            buckets.push(bucket);
+                        }
+                        current = Some((i / group, Bucket::new(ping.sent_time())));
+                    }
+                    if let Some((_, bucket)) = &mut current {
+                        // Going backwards, the start of the group is the last ping we see
+                        bucket.start = ping.sent_time();
+                        match ping {
+                            Ping::Received(_, lat) => {
+                                bucket.sent();
+                                bucket.received(lat);
+                                visible_lats.push(lat);
+                                if plot_style == PlotStyle::Scatter {
+                                    points.push(Some([
+                                        time_to_x(ping.sent_time()) + x_step / 2.,
+                                        lat_to_y(lat),
+                                    ]));
+                                }
+                            }
+                            Ping::Sent(time)
+                                if now.saturating_duration_since(time) >= LOSS_TIMEOUT =>
+                            {
+                                bucket.sent()
+                            }
+                            Ping::Sent(_) => {}
+                        }
+                    }
+                }
+                if let Some((_, mut bucket)) = current {
+                    bucket.finish();
+                    buckets.push(bucket);
+                }
+                (buckets, group_w, false)
+            } else {
+                // Use the finest tier whose buckets are at least a column wide
+                let tier = tiers
+                    .iter()
+                    .find(|t| t.period.as_secs_f64() / delay * x_step >= column_w)
+                    .unwrap_or(&tiers[tiers.len() - 1]);
+                let bucket_w = tier.period.as_secs_f64() / delay * x_step;
+                let buckets = tier
+                    .buckets()
+                    .iter()
+                    .rev()
+                    .skip_while(|b| b.start > end_time)
+                    .take_while(|b| time_to_x(b.start) + bucket_w >= graph_area.left())
+                    .cloned()
+                    .collect();
+                (buckets, bucket_w, true)
+            };
+
+            let nb_buckets = buckets.len();
+            if state.ids.bands.len() < nb_buckets {
+                state.update(|state| {
+                    let id_gen = &mut ui.widget_id_generator();
+                    state.ids.bands.resize(nb_buckets, id_gen);
+                    state.ids.band_tops.resize(nb_buckets, id_gen);
+                    state.ids.band_avgs.resize(nb_buckets, id_gen);
+                    state.ids.band_losses.resize(nb_buckets, id_gen);
+                });
+            }
+            let graph_color = self.style.color(ui.theme());
+            let bucket_w = bucket_w.max(1.);
+            for (i, bucket) in buckets.iter().enumerate() {
+                let x = time_to_x(bucket.start);
+                let mut fill = |from: [f64; 2], to: [f64; 2], color: Color, band_id| {
+                    if let Some(rct) = Rect::from_corners(from, to).overlap(graph_area) {
+                        widget::Rectangle::fill(rct.dim())
+                            .xy(rct.xy())
+                            .color(color)
+                            .parent(id)
+                            .graphics_for(id)
+                            .set(band_id, ui);
+                    }
+                };
+                if let (Some(min), Some(max), Some(avg)) = (bucket.min, bucket.max, bucket.avg()) {
+                    let avg_y = lat_to_y(avg);
+                    match plot_style {
+                        PlotStyle::Bars => {
+                            let band_color = threshold_color(avg).unwrap_or(graph_color);
+                            // Most latencies fall within the denser part of the band, up to the 99th percentile
+                            let p99_y = lat_to_y(bucket.p99.unwrap_or(max));
+                            fill(
+                                [x, lat_to_y(min)],
+                                [x + bucket_w, p99_y],
+                                band_color.alpha(0.5),
+                                state.ids.bands[i],
+                            );
+                            fill(
+                                [x, p99_y],
+                                [x + bucket_w, lat_to_y(max)],
+                                band_color.alpha(0.2),
+                                state.ids.band_tops[i],
+                            );
+                            fill(
+                                [x, avg_y - 0.5],
+                                [x + bucket_w, avg_y + 0.5],
+                                band_color,
+                                state.ids.band_avgs[i],
+                            );
+                        }
+                        PlotStyle::Line => points.push(Some([x + bucket_w / 2., avg_y])),
+                        PlotStyle::Scatter if from_tiers => {
+                            points.push(Some([x + bucket_w / 2., avg_y]))
+                        }
+                        PlotStyle::Scatter => {}
+                        PlotStyle::Heatmap => {
+                            let counts = bucket.histogram.counts();
+                            let max_count = counts.iter().copied().max().unwrap_or(0).max(1);
+                            for (bin, &count) in counts.iter().enumerate() {
+                                let (low, high) = Histogram::bin_range(bin);
+                                let cell = Rect::from_corners(
+                                    [x, lat_to_y(low)],
+                                    [x + bucket_w, lat_to_y(high)],
+                                );
+                                if let (true, Some(cell)) = (count > 0, cell.overlap(graph_area)) {
+                                    // Ranges with more latencies are more opaque
+                                    let alpha = 0.15 + 0.85 * count as f32 / max_count as f32;
+                                    plot.extend(rect_triangles(
+                                        cell,
+                                        graph_color.alpha(alpha).into(),
+                                    ));
+                                }
+                            }
+                        }
+                    }
+                    if from_tiers {
+                        // Individual latencies are gone, approximate with the averages
+                        visible_lats.push(avg);
+                        approx_lats = true;
+                    }
+                    min_lat = min_lat.min(min);
+                    max_lat = max_lat.max(max);
+                    avg_lat += bucket.total;
+                    nb_lat += bucket.received;
+                } else if plot_style == PlotStyle::Line {
+                    points.push(None);
+                }
+                if bucket.is_finished() && bucket.lost() > 0 {
+                    let loss = bucket.lost() as f32 / bucket.sent as f32;
+                    fill(
+                        [x, graph_area.bottom()],
+                        [x + bucket_w, graph_area.top()],
+                        missing_color.alpha(0.15 + 0.5 * loss),
+                        state.ids.band_losses[i],
+                    );
+                }
+            }
+        }
+
+        /* LINE AND SCATTER PLOTS */
+        let plot_color = self.style.color(ui.theme()).alpha(0.8).into();
+        match plot_style {
+            PlotStyle::Line => plot.extend(line_triangles(&points, graph_area, plot_color)),
+            PlotStyle::Scatter => {
+                // Dots drawn over one another can be skipped when zoomed out
+                let mut drawn = HashSet::new();
+                for point in points.iter().flatten() {
+                    let dot = Rect::from_xy_dim(*point, [SCATTER_DOT_SIZE; 2]);
+                    if graph_area.is_over(*point)
+                        && drawn.insert((point[0].round() as i64, point[1].round() as i64))
+                    {
+                        plot.extend(rect_triangles(dot, plot_color));
+                    }
+                }
+            }
+            PlotStyle::Bars | PlotStyle::Heatmap => {}
+        }
+        if !plot.is_empty() {
+            widget::Triangles::multi_color(plot)
+                .with_bounding_rect(graph_area)
+                .parent(id)
+                .graphics_for(id)
+                .set(state.ids.plot, ui);
+        }
+
         /* WIDGET BORDER */
         widget::Rectangle::outline_styled(
             graph_area.dim(),
@@ -274,6 +826,21 @@ impl Widget for LatencyGraphWidget<'_> {
         .graphics_for(id)
         .set(state.ids.border, ui);
 
+        /* LABEL */
+        if let Some(label) = self.label {
+            widget::Text::new(label)
+                .top_left_with_margins_on(
+                    id,
+                    GRAPH_AREA_PADDING.y.end + 4.,
+                    GRAPH_AREA_PADDING.x.start + 6.,
+                )
+                .font_size(10)
+                .color(border_color)
+                .parent(id)
+                .graphics_for(id)
+                .set(state.ids.label, ui);
+        }
+
         /* X TICKS */
         let tick_step = update_ticks_step(state.tick_step, x_step, self.settings.delay);
         if tick_step != state.tick_step {
@@ -282,43 +849,100 @@ impl Widget for LatencyGraphWidget<'_> {
 
         let tick_dist =
             TICK_STEPS[tick_step] as f64 * x_step / self.settings.delay.as_millis() as f64;
-        let x_tick_nb = (graph_area.w() / tick_dist).ceil() as usize;
-        if x_tick_nb > state.ids.x_ticks.len() {
-            state.update(|state| {
-                state
-                    .ids
-                    .x_ticks
-                    .resize(x_tick_nb, &mut ui.widget_id_generator());
-            });
-        }
-        for i in 0..x_tick_nb {
-            let x = graph_area.right() - i as f64 * tick_dist;
+        if let Some(last_index) = last_index.filter(|_| self.settings.clock_axis) {
+            // Ticks at round times of day, moving along with the bars
+            let step_ms = TICK_STEPS[tick_step] as i64;
+            let last_time = self.buffer[last_index].sent_time();
+            let last_ms = match self.buffer.wall_time(last_time).duration_since(UNIX_EPOCH) {
+                Ok(d) => d.as_millis() as i64,
+                Err(_) => 0,
+            };
+            let last_x = graph_area.right() - x_offset;
+            let px_per_ms = tick_dist / step_ms as f64;
+            // Round in local time so that minutes line up even with odd timezone offsets
+            let utc_offset_ms = Local::now().offset().fix().local_minus_utc() as i64 * 1000;
+            let right_ms = last_ms + (x_offset / px_per_ms) as i64;
+            let first_tick_ms = right_ms - (right_ms + utc_offset_ms).rem_euclid(step_ms);
+            let x_tick_nb = (graph_area.w() / tick_dist).ceil() as usize + 1;
+            if x_tick_nb > state.ids.x_ticks.len() || x_tick_nb > state.ids.x_tick_labels.len() {
+                state.update(|state| {
+                    state
+                        .ids
+                        .x_ticks
+                        .resize(x_tick_nb, &mut ui.widget_id_generator());
+                    state
+                        .ids
+                        .x_tick_labels
+                        .resize(x_tick_nb, &mut ui.widget_id_generator());
+                });
+            }
+            for i in 0..x_tick_nb {
+                let tick_ms = first_tick_ms - i as i64 * step_ms;
+                let x = last_x - (last_ms - tick_ms) as f64 * px_per_ms;
+                if x < graph_area.left() || x > graph_area.right() {
+                    continue;
+                }
 
-            widget::Line::abs([x, graph_area.bottom()], [x, graph_area.bottom() - 10.])
-                .color(border_color)
-                .parent(id)
-                .graphics_for(id)
-                .set(state.ids.x_ticks[i], ui);
+                widget::Line::abs([x, graph_area.bottom()], [x, graph_area.bottom() - 10.])
+                    .color(border_color)
+                    .parent(id)
+                    .graphics_for(id)
+                    .set(state.ids.x_ticks[i], ui);
 
-            if i == x_tick_nb - 1 {
-                let dur = Duration::from_millis(TICK_STEPS[tick_step] as u64 * i as u64);
-                widget::Text::new(&format!("{:?}", dur))
+                let format = if step_ms < 1000 {
+                    "%H:%M:%S%.3f"
+                } else {
+                    "%H:%M:%S"
+                };
+                widget::Text::new(&Local.timestamp_millis(tick_ms).format(format).to_string())
                     .xy([x, graph_area.bottom() - 20.])
-                    .wh([20., 20.])
+                    .wh([60., 20.])
                     .center_justify()
                     .font_size(8)
                     .color(border_color)
                     .parent(id)
                     .graphics_for(id)
-                    .set(state.ids.x_tick_label, ui);
+                    .set(state.ids.x_tick_labels[i], ui);
+            }
+        } else {
+            let x_tick_nb = (graph_area.w() / tick_dist).ceil() as usize;
+            if x_tick_nb > state.ids.x_ticks.len() {
+                state.update(|state| {
+                    state
+                        .ids
+                        .x_ticks
+                        .resize(x_tick_nb, &mut ui.widget_id_generator());
+                });
+            }
+            for i in 0..x_tick_nb {
+                let x = graph_area.right() - i as f64 * tick_dist;
+
+                widget::Line::abs([x, graph_area.bottom()], [x, graph_area.bottom() - 10.])
+                    .color(border_color)
+                    .parent(id)
+                    .graphics_for(id)
+                    .set(state.ids.x_ticks[i], ui);
+
+                if i == x_tick_nb - 1 {
+                    let dur = Duration::from_millis(TICK_STEPS[tick_step] * i as u64);
+                    widget::Text::new(&format!("{:?}", dur))
+                        .xy([x, graph_area.bottom() - 20.])
+                        .wh([20., 20.])
+                        .center_justify()
+                        .font_size(8)
+                        .color(border_color)
+                        .parent(id)
+                        .graphics_for(id)
+                        .set(state.ids.x_tick_label, ui);
+                }
             }
         }
 
         /* Y TICKS */
-        if nb_lat > 0 {
+        if let Some(avg_lat) = avg_lat.checked_div(nb_lat) {
             const TICK_LENGTH: f64 = 10.;
 
-            let mut set_tick = |lat: u128, rect: Rect, y: f64, tick_id, label_id| {
+            let mut set_tick = |lat: Duration, rect: Rect, y: f64, tick_id, label_id| {
                 widget::Line::abs(
                     [graph_area.right(), y],
                     [graph_area.right() + TICK_LENGTH, y],
@@ -339,7 +963,6 @@ impl Widget for LatencyGraphWidget<'_> {
                     .set(label_id, ui);
             };
 
-            let avg_lat = avg_lat / nb_lat;
             let avg_y = lat_to_y(avg_lat);
             let avg_rect =
                 Rect::from_xy_dim([graph_area.right() + TICK_LENGTH + 22., avg_y], [40., 10.]);
@@ -395,7 +1018,130 @@ impl Widget for LatencyGraphWidget<'_> {
                 .parent(id)
                 .graphics_for(id)
                 .set(state.ids.y_minmax_bar, ui);
+
+            /* PERCENTILES */
+            let percentiles = &self.settings.percentiles;
+            if percentiles.len() > state.ids.percentile_ticks.len() {
+                state.update(|state| {
+                    let id_gen = &mut ui.widget_id_generator();
+                    state.ids.percentile_ticks.resize(percentiles.len(), id_gen);
+                    state
+                        .ids
+                        .percentile_labels
+                        .resize(percentiles.len(), id_gen);
+                    state.ids.percentile_lines.resize(percentiles.len(), id_gen);
+                });
+            }
+            let mut sorted: Vec<(usize, f64)> = percentiles.iter().copied().enumerate().collect();
+            sorted.sort_by(|a, b| a.1.total_cmp(&b.1));
+            let mut min_label_y = graph_area.bottom();
+            for (i, p) in sorted {
+                let lat = match percentile(&mut visible_lats, p / 100.) {
+                    Some(lat) => lat,
+                    None => break,
+                };
+                let y = lat_to_y(lat);
+                if y > graph_area.top() {
+                    break;
+                }
+                // Ticks point inwards from the right edge, with labels pushed apart to stay readable
+                widget::Line::abs(
+                    [graph_area.right() - TICK_LENGTH, y],
+                    [graph_area.right(), y],
+                )
+                .color(border_color)
+                .parent(id)
+                .graphics_for(id)
+                .set(state.ids.percentile_ticks[i], ui);
+                let label_y = f64::max(y + 6., min_label_y);
+                min_label_y = label_y + 10.;
+                // Marked with a tilde when computed from the averages of the summaries
+                let approx = if approx_lats { "~" } else { "" };
+                widget::Text::new(&format!("{}p{} {}", approx, p, format_latency(lat)))
+                    .xy([graph_area.right() - TICK_LENGTH - 32., label_y])
+                    .wh([60., 10.])
+                    .right_justify()
+                    .font_size(8)
+                    .color(border_color)
+                    .parent(id)
+                    .graphics_for(id)
+                    .set(state.ids.percentile_labels[i], ui);
+                if self.settings.percentile_lines {
+                    widget::Line::abs([graph_area.left(), y], [graph_area.right(), y])
+                        .color(border_color.alpha(0.3))
+                        .dashed()
+                        .parent(id)
+                        .graphics_for(id)
+                        .set(state.ids.percentile_lines[i], ui);
+                }
+            }
+        }
+        /* TOOLTIP */
+        if let (Some((index, ping, x)), Some([mx, my])) = (hovered, mouse_xy) {
+            widget::Rectangle::fill([bar_width, graph_area.h()])
+                .x_y(x + bar_width / 2., graph_area.y())
+                .color(border_color.alpha(0.2))
+                .parent(id)
+                .graphics_for(id)
+                .set(state.ids.hover_bar, ui);
+
+            let sent = DateTime::<Local>::from(self.buffer.wall_time(ping.sent_time()));
+            let mut text = format!("#{}\n{}\n", index, sent.format("%H:%M:%S%.3f"));
+            match ping {
+                Ping::Received(_, lat) => {
+                    text += &format_latency(lat);
+                    let previous = index
+                        .checked_sub(1)
+                        .filter(|&prev| prev >= self.buffer.get_start_index())
+                        .map(|prev| self.buffer[prev]);
+                    if let Some(Ping::Received(_, prev_lat)) = previous {
+                        let sign = if lat >= prev_lat { '+' } else { '-' };
+                        text += &format!(" ({}{})", sign, format_latency(lat.abs_diff(prev_lat)));
+                    }
+                }
+                Ping::Sent(time) if now.saturating_duration_since(time) >= LOSS_TIMEOUT => {
+                    text += "lost"
+                }
+                Ping::Sent(_) => text += "pending",
+            }
+
+            // Keep the tooltip inside the graph, on the left of the cursor if there's no room
+            const TOOLTIP_SIZE: [f64; 2] = [120., 44.];
+            let mut tooltip_x = mx + 12. + TOOLTIP_SIZE[0] / 2.;
+            if tooltip_x + TOOLTIP_SIZE[0] / 2. > graph_area.right() {
+                tooltip_x = mx - 12. - TOOLTIP_SIZE[0] / 2.;
+            }
+            let tooltip_y = my.clamp(
+                graph_area.bottom() + TOOLTIP_SIZE[1] / 2.,
+                graph_area.top() - TOOLTIP_SIZE[1] / 2.,
+            );
+            widget::BorderedRectangle::new(TOOLTIP_SIZE)
+                .x_y(tooltip_x, tooltip_y)
+                .color(color::DARK_CHARCOAL.alpha(0.9))
+                .border(1.)
+                .border_color(border_color)
+                .parent(id)
+                .graphics_for(id)
+                .set(state.ids.tooltip, ui);
+            widget::Text::new(&text)
+                .top_left_with_margins_on(state.ids.tooltip, 4., 6.)
+                .font_size(10)
+                .color(border_color)
+                .parent(id)
+                .graphics_for(id)
+                .set(state.ids.tooltip_text, ui);
+        }
+
+        /* AUTO-FIT */
+        let fit_lat = match self.settings.y_fit {
+            YFit::Manual => None,
+            YFit::Max => Some(max_lat).filter(|_| nb_lat > 0),
+            YFit::P99 => percentile(&mut visible_lats, 0.99),
+        };
+        if fit_lat != state.fit_lat {
+            state.update(|state| state.fit_lat = fit_lat);
         }
+
         trace!(
             "Updating ringbuf over area {:?} widget with {} points, zoom: {:?}",
             graph_area,
@@ -403,23 +1149,156 @@ impl Widget for LatencyGraphWidget<'_> {
             zoom,
         );
 
-        zoom
+        GraphEvent {
+            zoom,
+            drag,
+            y_axis,
+            latencies: visible_lats,
+        }
+    }
+}
+
+impl YScale {
+    /// Scale selected after this one
+    pub fn next(self) -> YScale {
+        match self {
+            YScale::Linear => YScale::Sqrt,
+            YScale::Sqrt => YScale::Log,
+            YScale::Log => YScale::Linear,
+        }
+    }
+
+    /// Height of the given latency in milliseconds, in arbitrary units
+    fn transform(self, ms: f64) -> f64 {
+        match self {
+            YScale::Linear => ms,
+            YScale::Sqrt => ms.sqrt(),
+            YScale::Log => (ms / LOG_SCALE_FLOOR_MS).ln_1p(),
+        }
+    }
+}
+
+impl YAxis {
+    pub fn ms_to_y(&self, ms: f64) -> f64 {
+        self.bottom + self.scale.transform(ms) * self.unit
+    }
+
+    pub fn lat_to_y(&self, lat: Duration) -> f64 {
+        self.ms_to_y(lat.as_secs_f64() * 1000.)
     }
 }
 
+impl YFit {
+    /// Mode selected after this one
+    pub fn next(self) -> YFit {
+        match self {
+            YFit::Manual => YFit::Max,
+            YFit::Max => YFit::P99,
+            YFit::P99 => YFit::Manual,
+        }
+    }
+}
+
+impl PlotStyle {
+    /// Style selected after this one
+    pub fn next(self) -> PlotStyle {
+        match self {
+            PlotStyle::Bars => PlotStyle::Line,
+            PlotStyle::Line => PlotStyle::Scatter,
+            PlotStyle::Scatter => PlotStyle::Heatmap,
+            PlotStyle::Heatmap => PlotStyle::Bars,
+        }
+    }
+}
+
+/// Two triangles covering the rectangle
+fn rect_triangles(rect: Rect, color: Rgba) -> [Triangle<ColoredPoint>; 2] {
+    let (l, r, b, t) = rect.l_r_b_t();
+    let (first, second) = triangles::from_quad([[l, b], [l, t], [r, t], [r, b]]);
+    [first.color_all(color), second.color_all(color)]
+}
+
+/// Triangles drawing a line through consecutive points, broken where a point is missing and
+/// clipped to the area
+fn line_triangles(
+    points: &[Option<Point>],
+    area: Rect,
+    color: Rgba,
+) -> Vec<Triangle<ColoredPoint>> {
+    const HALF_THICKNESS: f64 = 0.75;
+    let mut line = Vec::new();
+    for pair in points.windows(2) {
+        let (a, b) = match (pair[0], pair[1]) {
+            (Some(a), Some(b)) => match clip_segment(a, b, area) {
+                Some(segment) => segment,
+                None => continue,
+            },
+            _ => continue,
+        };
+        let length = f64::hypot(b[0] - a[0], b[1] - a[1]);
+        if length == 0. {
+            continue;
+        }
+        // Offset of the sides of the segment from its middle
+        let n = [
+            -(b[1] - a[1]) / length * HALF_THICKNESS,
+            (b[0] - a[0]) / length * HALF_THICKNESS,
+        ];
+        let (first, second) = triangles::from_quad([
+            [a[0] + n[0], a[1] + n[1]],
+            [b[0] + n[0], b[1] + n[1]],
+            [b[0] - n[0], b[1] - n[1]],
+            [a[0] - n[0], a[1] - n[1]],
+        ]);
+        line.push(first.color_all(color));
+        line.push(second.color_all(color));
+    }
+    line
+}
+
+/// Cuts the segment at the left and right sides of the area, and flattens it at the top and
+/// bottom
+fn clip_segment(a: Point, b: Point, area: Rect) -> Option<(Point, Point)> {
+    let (a, b) = if a[0] <= b[0] { (a, b) } else { (b, a) };
+    if b[0] < area.left() || a[0] > area.right() {
+        return None;
+    }
+    let y_at = |x: f64| a[1] + (b[1] - a[1]) * (x - a[0]) / (b[0] - a[0]);
+    let clip = |point: Point| {
+        let x = point[0].clamp(area.left(), area.right());
+        let y = if x == point[0] { point[1] } else { y_at(x) };
+        [x, y.clamp(area.bottom(), area.top())]
+    };
+    Some((clip(a), clip(b)))
+}
+
+/// Horizontal distance between consecutive pings, for bars of the given width
+fn x_step(bar_width: f64) -> f64 {
+    if bar_width < 1. {
+        bar_width
+    } else {
+        bar_width + 1.
+    }
+}
+
+/// Time span covered by the given width in pixels at the given zoom
+pub fn pixels_to_duration(zoom: Zoom, delay: Duration, pixels: f64) -> Duration {
+    let x_step = x_step(f64::powi(ZOOM_BASE, zoom.0 as i32));
+    delay.mul_f64(pixels.abs() / x_step)
+}
+
 fn update_ticks_step(old_step: usize, step_width: f64, delay: Duration) -> usize {
-    let delay = delay.as_millis();
-    let step_width = step_width as u128;
+    let px_per_ms = step_width / (delay.as_secs_f64() * 1000.);
     let mut step = old_step;
     // Find the closest tick step that results in a distance within the given range
-    while TICK_STEPS[step] * step_width / delay > TICK_MAX_STEP {
+    while TICK_STEPS[step] as f64 * px_per_ms > TICK_MAX_STEP {
         if step > 0 {
             step -= 1;
         } else {
             break;
         }
     }
-    while TICK_STEPS[step] * step_width / delay < TICK_MIN_STEP {
+    while (TICK_STEPS[step] as f64) * px_per_ms < TICK_MIN_STEP {
         if step < TICK_STEPS.len() - 1 {
             step += 1;
         } else {
@@ -429,21 +1308,28 @@ fn update_ticks_step(old_step: usize, step_width: f64, delay: Duration) -> usize
     if old_step != step {
         debug!(
             "Updating tick_step: {:?} ({}) => {:?} ({}), pixel dist: {}",
-            Duration::from_millis(TICK_STEPS[old_step] as u64),
+            Duration::from_millis(TICK_STEPS[old_step]),
             old_step,
-            Duration::from_millis(TICK_STEPS[step] as u64),
+            Duration::from_millis(TICK_STEPS[step]),
             step,
-            TICK_STEPS[step] * step_width / delay
+            TICK_STEPS[step] as f64 * px_per_ms
         );
     }
     step
 }
 
-fn format_latency(lat: u128) -> String {
-    if lat < 1000 {
-        lat.to_string() + "ms"
-    } else if lat < 60000 {
-        format!("{:.2}s", lat as f32 / 1000.)
+fn format_latency(lat: Duration) -> String {
+    let ms = lat.as_secs_f64() * 1000.;
+    if ms < 1. {
+        format!("{:.3}ms", ms)
+    } else if ms < 10. {
+        format!("{:.2}ms", ms)
+    } else if ms < 100. {
+        format!("{:.1}ms", ms)
+    } else if ms < 1000. {
+        format!("{:.0}ms", ms)
+    } else if ms < 60000. {
+        format!("{:.2}s", ms / 1000.)
     } else {
         String::from(">1m")
     }